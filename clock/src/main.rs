@@ -2,26 +2,46 @@
 #![no_main]
 #![feature(type_alias_impl_trait)]
 
-use clocklib::ClockDisplay;
+use clocklib::alarm::{next_alarm, AlarmTime, MAX_ALARMS};
+use clocklib::animation::{Animation, Player};
+use clocklib::calendar;
+use clocklib::console::{self, Command};
+use clocklib::gesture::{self, Gesture, Recognizer};
+use clocklib::glyphs::{progress_bar, Icon, CH_LTR, DIGITS, WEEKDAYS};
+use clocklib::orientation::Orientation;
+use clocklib::separator::Separator;
+use clocklib::settings::{HourFormat, Journal, Settings};
+use clocklib::seven_segment;
+use clocklib::timer::{Countdown, Stopwatch};
+use clocklib::transition::{Direction, Style, Transition};
+use clocklib::{ClockDisplay, Symbol, CELLS, SEGMENTS};
+use core::fmt::Write;
 use defmt::*;
 use embassy_executor::Spawner;
 use embassy_futures::select::select;
 use embassy_futures::select::Either;
+use embassy_rp::bind_interrupts;
+use embassy_rp::flash::{Blocking as FlashBlocking, Flash, ERASE_SIZE};
 use embassy_rp::gpio;
 use embassy_rp::gpio::AnyPin;
 use embassy_rp::gpio::Pin;
 use embassy_rp::i2c::Blocking;
 use embassy_rp::i2c::I2c;
 use embassy_rp::i2c::{self, Config};
-use embassy_rp::peripherals::I2C0;
+use embassy_rp::peripherals::{FLASH, I2C0, USB};
+use embassy_rp::usb::{self, Driver};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
-use embassy_time::{Duration, Ticker, Timer};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use embassy_usb::class::cdc_acm::{self, CdcAcmClass};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::UsbDevice;
 use gpio::{Input, Level, Output, Pull};
+use heapless::{String, Vec};
 use is31fl3731_driver::IS31FL3731;
 use pcf8563::*;
 use shared_bus::I2cProxy;
@@ -30,59 +50,132 @@ use static_cell::make_static;
 use {defmt_rtt as _, panic_probe as _};
 
 type StaticClockDisplay = ClockDisplay<I2cProxy<'static, NullMutex<I2c<'static, I2C0, Blocking>>>>;
-type StaticRtc =
-    Mutex<NoopRawMutex, PCF8563<I2cProxy<'static, NullMutex<I2c<'static, I2C0, Blocking>>>>>;
+type Rtc = PCF8563<I2cProxy<'static, NullMutex<I2c<'static, I2C0, Blocking>>>>;
+type StaticRtc = Mutex<NoopRawMutex, Rtc>;
+type StaticFlash = Flash<'static, FLASH, FlashBlocking, FLASH_SIZE>;
+type UsbDriver = Driver<'static, USB>;
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => usb::InterruptHandler<USB>;
+});
 
 #[embassy_executor::task]
 async fn screen_update(mut clock: StaticClockDisplay) {
     let mut blink: Option<BlinkData> = None;
+    let mut shown: Option<[Symbol; 4]> = None;
 
     loop {
         let time = *CURRENT_TIME.lock().await;
-        let brightness_level = *CURRENT_BRIGHTNESS.lock().await;
-        let brightness = BRIGHTNESS_MAP[brightness_level];
+        let state = { CURRENT_STATE.lock().await.clone() };
+        let countdown = *COUNTDOWN.lock().await;
+        let stopwatch = *STOPWATCH.lock().await;
+        let settings = *SETTINGS.lock().await;
+        let text = *CONSOLE_TEXT.lock().await;
+        let now = Instant::now().as_millis();
+        let brightness = BRIGHTNESS_MAP[settings.brightness as usize];
+
+        // changed over the console, the cells are drawn afresh
+        if clock.orientation != settings.orientation {
+            clock.orientation = settings.orientation;
+            shown = None;
+        }
 
         info!(
-            "Screen refresh: {}:{}:{} {})",
-            time.hours, time.minutes, time.seconds, blink
+            "Screen refresh: {}-{}-{} {}:{}:{} {} {})",
+            time.year, time.month, time.day, time.hours, time.minutes, time.seconds, state, blink
         );
 
-        let digits: [usize; 4] = [
-            (time.hours / 10).into(),
-            (time.hours % 10).into(),
-            (time.minutes / 10).into(),
-            (time.minutes % 10).into(),
-        ];
+        let (symbols, separator) =
+            screen_content(&state, &time, &countdown, &stopwatch, &settings, &text, now);
+
+        // animate cells that changed since the last refresh, unless blinking
+        // or turned off
+        let mut transitions: [Option<Transition>; 4] = [None; 4];
+        if let (Some(shown), None, true) = (shown, &blink, settings.transitions) {
+            for (i, transition) in transitions.iter_mut().enumerate() {
+                if shown[i] != symbols[i] {
+                    transition.replace(Transition::new(
+                        shown[i],
+                        symbols[i],
+                        DIGIT_TRANSITIONS[i],
+                        TRANSITION_FRAMES,
+                    ));
+                }
+            }
+        }
 
-        for (i, digit) in digits.iter().enumerate() {
+        for frame in 1..TRANSITION_FRAMES {
+            if transitions.iter().all(Option::is_none) {
+                break;
+            }
+
+            for (i, transition) in transitions.iter().enumerate() {
+                if let Some(transition) = transition {
+                    clock
+                        .draw_levels(i as u8, &transition.levels(frame), brightness)
+                        .unwrap();
+                }
+            }
+            Timer::after(Duration::from_millis(TRANSITION_FRAME_MS)).await;
+        }
+
+        for (i, symbol) in symbols.iter().enumerate() {
             let mut color = brightness;
 
-            if let Some(blink) = &blink {
-                match (blink.frame, blink.position, i) {
-                    (0, 0, 2..=3) => {
-                        color = 0x02;
-                    }
-                    (0, 1, 0..=1) => {
-                        color = 0x02;
-                    }
-                    _ => {}
+            // dim the cells being set every other blink frame
+            if let Some(BlinkData { frame: 0 }) = &blink {
+                if blinking_cells(&state).contains(&i) {
+                    color = 0x02;
                 }
             }
 
-            clock.draw_symbol(i as u8, *digit, color).unwrap();
+            clock.draw_glyph(i as u8, symbol, color).unwrap();
         }
-
-        let refresh_signal = select(
-            Timer::after(Duration::from_millis(20 * 1000)),
-            SCREEN_REFRESH_SIGNAL.wait(),
-        )
-        .await;
+        shown = Some(symbols);
+
+        // the colon pulses once per second while idle or the countdown is
+        // paused and stays lit otherwise, the cells are redrawn every 20
+        // seconds
+        let pulse = match state {
+            State::Idle => true,
+            State::Countdown => countdown.is_started() && !countdown.is_running(),
+            _ => false,
+        };
+        let mut colon_lit = true;
+        let mut colon_ticks = 0;
+        let refresh_signal = loop {
+            clock
+                .draw_separator(separator, if colon_lit { brightness } else { 0x00 })
+                .unwrap();
+
+            let tick = select(
+                Timer::after(Duration::from_millis(COLON_PULSE_MS)),
+                SCREEN_REFRESH_SIGNAL.wait(),
+            )
+            .await;
+
+            match tick {
+                Either::First(_) => {
+                    colon_ticks += 1;
+                    if colon_ticks * COLON_PULSE_MS >= 20 * 1000 {
+                        break Either::First(());
+                    }
+                    colon_lit = !pulse || !colon_lit;
+                }
+                Either::Second(refresh) => break Either::Second(refresh),
+            }
+        };
 
         match refresh_signal {
             Either::Second(ScreenRefresh::Blink(blink_data)) => {
                 blink.replace(blink_data);
             }
             Either::Second(ScreenRefresh::TimeChanged) => {}
+            Either::Second(ScreenRefresh::SelfTest) => {
+                self_test(&mut clock, brightness).await;
+                blink = None;
+                shown = None;
+            }
             Either::First(_) | Either::Second(ScreenRefresh::Normal) => blink = None,
         }
     }
@@ -93,7 +186,7 @@ async fn sync_time(rtc: &'static StaticRtc) {
     loop {
         let synced_time = {
             let mut rtc = rtc.lock().await;
-            rtc.get_datetime().unwrap()
+            read_rtc(&mut rtc)
         };
 
         {
@@ -101,6 +194,8 @@ async fn sync_time(rtc: &'static StaticRtc) {
             *time = synced_time;
         }
 
+        program_alarm(&mut *rtc.lock().await).await;
+
         Timer::after(Duration::from_secs(60 * 10)).await;
     }
 }
@@ -136,21 +231,261 @@ async fn led_numbers_test(mut clock: StaticClockDisplay) {
     }
 }
 
-async fn wait_for_low_debounced(button: &mut Input<'_, AnyPin>) {
+/// Lights every segment of all cells in turn, then the separator dots and
+/// finally everything, to spot dead LEDs.
+async fn self_test(clock: &mut StaticClockDisplay, color: u8) {
+    let mut levels = [0; SEGMENTS.len()];
+    clock.draw_separator(Separator::Off, color).unwrap();
+
+    for segment in 0..SEGMENTS.len() {
+        levels.fill(0);
+        levels[segment] = 0xFF;
+        for cell in 0..CELLS as u8 {
+            clock.draw_levels(cell, &levels, color).unwrap();
+        }
+        Timer::after(Duration::from_millis(SELF_TEST_STEP_MS)).await;
+    }
+
+    levels.fill(0);
+    for cell in 0..CELLS as u8 {
+        clock.draw_levels(cell, &levels, color).unwrap();
+    }
+    for dot in [Separator::UpperDot, Separator::LowerDot] {
+        clock.draw_separator(dot, color).unwrap();
+        Timer::after(Duration::from_millis(SELF_TEST_STEP_MS)).await;
+    }
+
+    levels.fill(0xFF);
+    for cell in 0..CELLS as u8 {
+        clock.draw_levels(cell, &levels, color).unwrap();
+    }
+    clock.draw_separator(Separator::Colon, color).unwrap();
+    Timer::after(Duration::from_millis(SELF_TEST_HOLD_MS)).await;
+}
+
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) {
+    usb.run().await;
+}
+
+/// Answers console commands while a terminal is connected.
+#[embassy_executor::task]
+async fn console_task(mut class: CdcAcmClass<'static, UsbDriver>, rtc: &'static StaticRtc) {
     loop {
-        button.wait_for_low().await;
-        let b = select(
-            button.wait_for_high(),
-            Timer::after(Duration::from_millis(50)),
+        class.wait_connection().await;
+        info!("Console connected");
+        let _ = console_session(&mut class, rtc).await;
+        info!("Console disconnected");
+    }
+}
+
+/// Reads lines ended by CR or LF and answers each, until the terminal
+/// disconnects. Nothing is echoed.
+async fn console_session(
+    class: &mut CdcAcmClass<'static, UsbDriver>,
+    rtc: &'static StaticRtc,
+) -> Result<(), EndpointError> {
+    let mut packet = [0; USB_PACKET_SIZE as usize];
+    let mut line: Vec<u8, CONSOLE_LINE_LEN> = Vec::new();
+    let mut overflow = false;
+
+    loop {
+        let len = class.read_packet(&mut packet).await?;
+
+        for &byte in &packet[..len] {
+            if byte != b'\r' && byte != b'\n' {
+                overflow |= line.push(byte).is_err();
+                continue;
+            }
+
+            let mut reply: String<CONSOLE_REPLY_LEN> = String::new();
+            let mut bootloader = false;
+            match core::str::from_utf8(&line) {
+                _ if overflow => {
+                    let _ = write!(reply, "error: line too long\r\n");
+                }
+                // the second half of a CR LF
+                Ok(text) if text.trim().is_empty() => {}
+                Ok(text) => match console::parse(text) {
+                    Ok(command) => {
+                        info!("Console: {}", text);
+                        bootloader = command == Command::Bootloader;
+                        if run_command(command, rtc, &mut reply).await.is_err() {
+                            reply.clear();
+                            let _ = write!(reply, "error: reply too long\r\n");
+                        }
+                    }
+                    Err(error) => {
+                        let _ = write!(reply, "error: {}\r\n", error);
+                    }
+                },
+                Err(_) => {
+                    let _ = write!(reply, "error: not UTF-8\r\n");
+                }
+            }
+            line.clear();
+            overflow = false;
+
+            write_reply(class, reply.as_bytes()).await?;
+            if bootloader {
+                // let the host read the reply before the device goes away
+                Timer::after(Duration::from_millis(100)).await;
+                embassy_rp::rom_data::reset_to_usb_boot(0, 0);
+            }
+        }
+    }
+}
+
+/// Runs a console command and writes its reply.
+async fn run_command(
+    command: Command,
+    rtc: &'static StaticRtc,
+    reply: &mut String<CONSOLE_REPLY_LEN>,
+) -> core::fmt::Result {
+    match command {
+        Command::Help => reply
+            .push_str(console::HELP)
+            .map_err(|_| core::fmt::Error)?,
+        // the time and date are read from the RTC, the software clock may be
+        // up to a second off
+        Command::Time(None) => console::write_time(reply, &read_rtc(&mut *rtc.lock().await))?,
+        Command::Time(Some((hours, minutes, seconds))) => {
+            change_time(rtc, |time| {
+                time.hours = hours;
+                time.minutes = minutes;
+                time.seconds = seconds;
+            })
+            .await
+        }
+        Command::Hold((hours, minutes, seconds)) => {
+            rtc.lock().await.control_clock(Control::Off).unwrap();
+            change_time(rtc, |time| {
+                time.hours = hours;
+                time.minutes = minutes;
+                time.seconds = seconds;
+            })
+            .await
+        }
+        Command::Start => {
+            // the software clock runs on from the held time, its next
+            // second in a second like the RTC's
+            *CURRENT_TIME.lock().await = read_rtc(&mut *rtc.lock().await);
+            TIME_STARTED.signal(());
+            Timer::after(Duration::from_millis(1000 - RTC_FIRST_SECOND_MS)).await;
+            rtc.lock().await.control_clock(Control::On).unwrap();
+        }
+        Command::Date(None) => console::write_date(reply, &read_rtc(&mut *rtc.lock().await))?,
+        Command::Date(Some((year, month, day))) => {
+            change_time(rtc, |time| {
+                time.year = year;
+                time.month = month;
+                time.day = day;
+                time.normalize();
+            })
+            .await
+        }
+        Command::Brightness(None) => {
+            write!(reply, "{}\r\n", SETTINGS.lock().await.brightness)?;
+        }
+        Command::Brightness(Some(level)) => {
+            if level as usize >= MAX_BRIGHTNESS_LEVEL {
+                return write!(
+                    reply,
+                    "error: levels go from 0 to {}\r\n",
+                    MAX_BRIGHTNESS_LEVEL - 1
+                );
+            }
+            SETTINGS.lock().await.brightness = level;
+            SETTINGS_CHANGED.signal(());
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+        Command::Orientation(None) => {
+            console::write_orientation(reply, SETTINGS.lock().await.orientation)?;
+        }
+        Command::Orientation(Some(orientation)) => {
+            SETTINGS.lock().await.orientation = orientation;
+            SETTINGS_CHANGED.signal(());
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+        Command::Transitions(None) => {
+            console::write_on_off(reply, SETTINGS.lock().await.transitions)?;
+        }
+        Command::Transitions(Some(transitions)) => {
+            SETTINGS.lock().await.transitions = transitions;
+            SETTINGS_CHANGED.signal(());
+        }
+        Command::UtcOffset(None) => {
+            console::write_utc_offset(reply, SETTINGS.lock().await.utc_offset)?;
+        }
+        Command::UtcOffset(Some(offset)) => {
+            SETTINGS.lock().await.utc_offset = offset;
+            SETTINGS_CHANGED.signal(());
+        }
+        Command::Show(symbols) => {
+            *CONSOLE_TEXT.lock().await = symbols;
+            to_state(State::ShowingText).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+        Command::SelfTest => SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::SelfTest),
+        Command::Settings => console::write_settings(reply, &*SETTINGS.lock().await)?,
+        Command::Alarms => console::write_alarms(reply, &SETTINGS.lock().await.alarms)?,
+        // restarts once the reply is sent
+        Command::Bootloader => {}
+    }
+
+    write!(reply, "ok\r\n")
+}
+
+/// Sends a reply in packets, a full last packet is followed by an empty one
+/// so the host sees the end of the transfer.
+async fn write_reply(
+    class: &mut CdcAcmClass<'static, UsbDriver>,
+    reply: &[u8],
+) -> Result<(), EndpointError> {
+    let max = class.max_packet_size() as usize;
+    for chunk in reply.chunks(max) {
+        class.write_packet(chunk).await?;
+    }
+    if !reply.is_empty() && reply.len() % max == 0 {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
+
+/// Writes the settings once they stopped changing for a while, so paging
+/// through a menu costs a single record.
+#[embassy_executor::task]
+async fn settings_task(mut flash: StaticFlash, mut journal: Journal) {
+    let mut saved = *SETTINGS.lock().await;
+
+    loop {
+        SETTINGS_CHANGED.wait().await;
+        while let Either::First(_) = select(
+            SETTINGS_CHANGED.wait(),
+            Timer::after(Duration::from_millis(SETTINGS_SAVE_DELAY_MS)),
         )
-        .await;
+        .await
+        {}
+
+        let settings = *SETTINGS.lock().await;
+        if settings != saved {
+            let (slot, sequence) = journal.store(&mut flash, &settings).unwrap();
+            info!("Settings saved: slot {} sequence {}", slot, sequence);
+            saved = settings;
+        }
+    }
+}
 
-        if let Either::First(_) = b {
-            // info!("Bounce");
-            continue;
-        } else {
-            break;
+/// Plays an animation to its end, an animation that loops never returns.
+async fn play_animation(clock: &mut StaticClockDisplay, data: &[u8], color: u8) {
+    let animation = unwrap!(Animation::new(data).ok());
+    let mut player = Player::new(animation);
+
+    while let Some(duration) = player.step() {
+        for (i, levels) in player.levels().iter().enumerate() {
+            clock.draw_levels(i as u8, levels, color).unwrap();
         }
+        Timer::after(Duration::from_millis(duration.into())).await;
     }
 }
 
@@ -158,40 +493,309 @@ async fn wait_for_low_debounced(button: &mut Input<'_, AnyPin>) {
 enum Event {
     SetButton(ButtonPress),
     AdjustButton(ButtonPress),
+    BothButtons,
+    AlarmRinging,
+    CountdownExpired,
 }
 
 #[derive(Format)]
 enum ButtonPress {
     Short,
+    Double,
     Long,
+    Repeat,
 }
 
 async fn on_event(event: Event, rtc: &'static StaticRtc) {
     let state = { CURRENT_STATE.lock().await.clone() };
 
     match (event, state) {
+        // An alarm interrupts whatever is going on
+        (Event::AlarmRinging, _) => {
+            to_state(State::Ringing).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Snooze with a short press of any button
+        (Event::SetButton(ButtonPress::Short), State::Ringing)
+        | (Event::AdjustButton(ButtonPress::Short), State::Ringing) => {
+            let now = *CURRENT_TIME.lock().await;
+            *SNOOZE.lock().await = Some(AlarmTime::after(&now, SNOOZE_MINUTES));
+            program_alarm(&mut *rtc.lock().await).await;
+            to_state(after_ringing().await).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Dismiss with a long press of any button or both together
+        (Event::SetButton(ButtonPress::Long), State::Ringing)
+        | (Event::AdjustButton(ButtonPress::Long), State::Ringing)
+        | (Event::BothButtons, State::Ringing) => {
+            *SNOOZE.lock().await = None;
+            program_alarm(&mut *rtc.lock().await).await;
+            to_state(after_ringing().await).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Both buttons go back to the time from anywhere, leaving setting
+        // modes like a long press of set does
+        (Event::BothButtons, state) => {
+            if let State::CountdownAlert = state {
+                COUNTDOWN.lock().await.reset();
+            }
+            program_alarm(&mut *rtc.lock().await).await;
+            to_state(State::Idle).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // The countdown ran out, while an alarm rings the alert waits for
+        // it to stop, see after_ringing
+        (Event::CountdownExpired, State::Ringing) => {}
+        (Event::CountdownExpired, _) => {
+            to_state(State::CountdownAlert).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Acknowledge the countdown alert with any button
+        (Event::SetButton(_), State::CountdownAlert)
+        | (Event::AdjustButton(_), State::CountdownAlert) => {
+            COUNTDOWN.lock().await.reset();
+            to_state(State::Countdown).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Move on to the stopwatch, the countdown keeps running in the
+        // background
+        (Event::SetButton(ButtonPress::Short), State::Countdown) => {
+            to_state(State::Stopwatch).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Start, pause or resume
+        (Event::AdjustButton(ButtonPress::Short), State::Countdown) => {
+            let now = Instant::now().as_millis();
+            let mut countdown = COUNTDOWN.lock().await;
+            if countdown.is_running() {
+                countdown.pause(now);
+            } else {
+                countdown.start(now);
+            }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Reset
+        (Event::AdjustButton(ButtonPress::Long), State::Countdown) => {
+            COUNTDOWN.lock().await.reset();
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Enter countdown setting mode, stopping the countdown
+        (Event::SetButton(ButtonPress::Long), State::Countdown) => {
+            COUNTDOWN.lock().await.reset();
+            to_state(State::SettingCountdown(0)).await;
+        }
+
+        // Exit countdown setting mode
+        (Event::SetButton(ButtonPress::Long), State::SettingCountdown(_))
+        | (Event::SetButton(ButtonPress::Short), State::SettingCountdown(1)) => {
+            to_state(State::Countdown).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        (Event::SetButton(ButtonPress::Short), State::SettingCountdown(_)) => {
+            to_state(State::SettingCountdown(1)).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Advance minutes or seconds
+        (Event::AdjustButton(ButtonPress::Short), State::SettingCountdown(position)) => {
+            let mut countdown = COUNTDOWN.lock().await;
+            let seconds = countdown.duration() / 1000;
+            let (mut minutes, mut seconds) = (seconds / 60, seconds % 60);
+            if position == 0 {
+                minutes = (minutes + 1) % 100;
+            } else {
+                seconds = (seconds + COUNTDOWN_SECONDS_STEP) % 60;
+            }
+            countdown.set_duration((minutes * 60 + seconds) * 1000);
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Move on to the settings, the stopwatch keeps running in the
+        // background
+        (Event::SetButton(ButtonPress::Short), State::Stopwatch) => {
+            to_state(State::Settings(0)).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Start or stop
+        (Event::AdjustButton(ButtonPress::Short), State::Stopwatch) => {
+            let now = Instant::now().as_millis();
+            let mut stopwatch = STOPWATCH.lock().await;
+            if stopwatch.is_running() {
+                stopwatch.stop(now);
+            } else {
+                stopwatch.start(now);
+            }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Lap while running, reset while stopped
+        (Event::SetButton(ButtonPress::Long), State::Stopwatch) => {
+            let now = Instant::now().as_millis();
+            let mut stopwatch = STOPWATCH.lock().await;
+            if stopwatch.is_running() {
+                stopwatch.lap(now);
+            } else {
+                stopwatch.reset();
+            }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Review the laps, newest first
+        (Event::AdjustButton(ButtonPress::Long), State::Stopwatch) => {
+            if !STOPWATCH.lock().await.laps().is_empty() {
+                to_state(State::ReviewingLaps(0)).await;
+                SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+            }
+        }
+
+        (Event::AdjustButton(ButtonPress::Short), State::ReviewingLaps(lap)) => {
+            let laps = STOPWATCH.lock().await.laps().len() as u8;
+            to_state(State::ReviewingLaps((lap + 1) % laps)).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        (Event::SetButton(_), State::ReviewingLaps(_)) => {
+            to_state(State::Stopwatch).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Move to the next setting, the last one goes back to the time
+        (Event::SetButton(ButtonPress::Short), State::Settings(position)) => {
+            if position + 1 < SETTINGS_POSITIONS {
+                to_state(State::Settings(position + 1)).await;
+            } else {
+                to_state(State::Idle).await;
+            }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        (Event::SetButton(ButtonPress::Long), State::Settings(_)) => {
+            to_state(State::Idle).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Change the setting
+        (Event::AdjustButton(ButtonPress::Short), State::Settings(position)) => {
+            adjust_settings(position).await;
+        }
+
+        // Any button dismisses text sent over the console
+        (Event::SetButton(_), State::ShowingText)
+        | (Event::AdjustButton(_), State::ShowingText) => {
+            to_state(State::Idle).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Enter alarm setting mode
+        (Event::AdjustButton(ButtonPress::Long), State::Idle) => {
+            to_state(State::SettingAlarm(0, 0)).await;
+        }
+
+        // Exit alarm setting mode
+        (Event::SetButton(ButtonPress::Long), State::SettingAlarm(..)) => {
+            program_alarm(&mut *rtc.lock().await).await;
+            to_state(State::Idle).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Move to the next setting, then to the next alarm
+        (Event::SetButton(ButtonPress::Short), State::SettingAlarm(alarm, position)) => {
+            if position + 1 < ALARM_POSITIONS {
+                to_state(State::SettingAlarm(alarm, position + 1)).await;
+            } else if (alarm as usize) + 1 < MAX_ALARMS {
+                to_state(State::SettingAlarm(alarm + 1, 0)).await;
+            } else {
+                program_alarm(&mut *rtc.lock().await).await;
+                to_state(State::Idle).await;
+                SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+                return;
+            }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+        }
+
+        // Change the setting
+        (Event::AdjustButton(ButtonPress::Short), State::SettingAlarm(alarm, position)) => {
+            adjust_alarm(alarm, position).await;
+        }
+
         // Enter time setting mode
         (Event::SetButton(ButtonPress::Long), State::Idle) => to_state(State::SettingTime(0)).await,
 
         // Exit time setting mode
         (Event::SetButton(ButtonPress::Long), State::SettingTime(_)) => to_state(State::Idle).await,
 
-        // Move to next position
+        // Exit date setting mode
+        (Event::SetButton(ButtonPress::Long), State::SettingDate(_)) => {
+            to_state(State::Idle).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Move to next position, the date follows the time
         (Event::SetButton(ButtonPress::Short), State::SettingTime(digit)) => {
-            let next_digit = (digit + 1) % 3; // hours, minutes, done
+            let next_digit = digit + 1; // minutes, hours, then the date
             if next_digit == 2 {
-                SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
-                to_state(State::Idle).await;
+                to_state(State::SettingDate(0)).await;
             } else {
                 to_state(State::SettingTime(next_digit)).await;
             }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
         }
 
-        // Advance hours or minutes
-        (Event::AdjustButton(ButtonPress::Short), State::SettingTime(digit)) => {
+        (Event::SetButton(ButtonPress::Short), State::SettingDate(position)) => {
+            let next_position = position + 1; // year, month, day, done
+            if next_position == 3 {
+                to_state(State::Idle).await;
+                SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+            } else {
+                to_state(State::SettingDate(next_position)).await;
+                SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+            }
+        }
+
+        // Advance hours or minutes, holding the button keeps advancing
+        (Event::AdjustButton(ButtonPress::Short), State::SettingTime(digit))
+        | (Event::AdjustButton(ButtonPress::Repeat), State::SettingTime(digit)) => {
             advance_time(digit, rtc).await;
         }
 
+        // Advance year, month or day
+        (Event::AdjustButton(ButtonPress::Short), State::SettingDate(position)) => {
+            advance_date(position, rtc).await;
+        }
+
+        // Page through the date views, the countdown, the stopwatch and the
+        // settings
+        (Event::SetButton(ButtonPress::Short), State::Idle) => {
+            to_state(State::ShowingDate(0)).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        (Event::SetButton(ButtonPress::Short), State::ShowingDate(page)) => {
+            if page + 1 == DATE_PAGES {
+                to_state(State::Countdown).await;
+            } else {
+                to_state(State::ShowingDate(page + 1)).await;
+            }
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
+        // Straight to the settings
+        (Event::SetButton(ButtonPress::Double), State::Idle) => {
+            to_state(State::Settings(0)).await;
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
+        }
+
         // Adjust brightness
         (Event::AdjustButton(ButtonPress::Short), State::Idle) => adjust_brightness().await,
 
@@ -200,36 +804,141 @@ async fn on_event(event: Event, rtc: &'static StaticRtc) {
 }
 
 async fn advance_time(position: u8, rtc: &'static StaticRtc) {
+    info!("Advancing time");
+
+    change_time(rtc, |current| {
+        if position == 0 {
+            let minutes = (current.minutes + 1) % 60;
+            current.minutes = minutes;
+            current.seconds = 0;
+        } else if position == 1 {
+            let hours = (current.hours + 1) % 24;
+            current.hours = hours;
+        }
+    })
+    .await;
+}
+
+async fn advance_date(position: u8, rtc: &'static StaticRtc) {
+    info!("Advancing date");
+
+    change_time(rtc, |current| {
+        if position == 0 {
+            current.year += 1;
+            if current.year >= calendar::BASE_YEAR + calendar::RTC_YEARS {
+                current.year = calendar::BASE_YEAR;
+            }
+        } else if position == 1 {
+            current.month = current.month % 12 + 1;
+        } else if position == 2 {
+            current.day = current.day % calendar::days_in_month(current.year, current.month) + 1;
+        }
+        // keeps the day inside the month and derives the weekday
+        current.normalize();
+    })
+    .await;
+}
+
+/// Changes the time read from the RTC with `change` and writes it back.
+async fn change_time(rtc: &'static StaticRtc, change: impl FnOnce(&mut calendar::DateTime)) {
     let mut rtc = rtc.lock().await;
-    let mut current = rtc.get_datetime().unwrap();
-    let defaut = default_datetime();
+    let mut current = read_rtc(&mut rtc);
+    change(&mut current);
 
-    current.month = defaut.month;
-    current.weekday = defaut.weekday;
-    current.year = defaut.year;
+    write_rtc(&mut rtc, &current);
+    *CURRENT_TIME.lock().await = current;
+    program_alarm(&mut rtc).await;
+    SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+}
 
-    info!("Advancing time");
+async fn adjust_alarm(alarm: u8, position: u8) {
+    let mut settings = SETTINGS.lock().await;
+    let alarm = &mut settings.alarms[alarm as usize];
 
-    if position == 0 {
-        let minutes = (current.minutes + 1) % 60;
-        current.minutes = minutes;
-        current.seconds = 0;
-    } else if position == 1 {
-        let hours = (current.hours + 1) % 24;
-        current.hours = hours;
+    match position {
+        0 => alarm.enabled = !alarm.enabled,
+        1 => alarm.hours = (alarm.hours + 1) % 24,
+        2 => alarm.minutes = (alarm.minutes + 1) % 60,
+        weekday => alarm.weekdays ^= 1 << (weekday - 3),
     }
 
-    rtc.set_datetime(&current).unwrap();
-    *CURRENT_TIME.lock().await = current;
+    SETTINGS_CHANGED.signal(());
+    SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+}
+
+/// Programs the PCF8563 with the alarm or snooze ringing next, or turns
+/// the alarm off when there is none.
+async fn program_alarm(rtc: &mut Rtc) {
+    let now = *CURRENT_TIME.lock().await;
+    let alarms = SETTINGS.lock().await.alarms;
+    let snooze = *SNOOZE.lock().await;
+
+    let next = match (next_alarm(&alarms, &now).map(|(_, time)| time), snooze) {
+        (Some(alarm), Some(snooze)) if snooze.minutes_from(&now) < alarm.minutes_from(&now) => {
+            Some(snooze)
+        }
+        (Some(alarm), _) => Some(alarm),
+        (None, snooze) => snooze,
+    };
+
+    // the alarm flag is left for alarm_task, which rings when it is set
+    match next {
+        Some(time) => {
+            info!(
+                "Next alarm: day {} {}:{}",
+                time.weekday, time.hours, time.minutes
+            );
+            rtc.set_alarm_minutes(time.minutes).unwrap();
+            rtc.set_alarm_hours(time.hours).unwrap();
+            rtc.set_alarm_weekday(time.weekday).unwrap();
+            rtc.control_alarm_minutes(Control::On).unwrap();
+            rtc.control_alarm_hours(Control::On).unwrap();
+            rtc.control_alarm_weekday(Control::On).unwrap();
+            rtc.control_alarm_day(Control::Off).unwrap();
+        }
+        None => rtc.disable_all_alarms().unwrap(),
+    }
+}
+
+async fn adjust_settings(position: u8) {
+    let mut settings = SETTINGS.lock().await;
+
+    match position {
+        0 => {
+            settings.hour_format = match settings.hour_format {
+                HourFormat::TwentyFour => HourFormat::Twelve,
+                HourFormat::Twelve => HourFormat::TwentyFour,
+            }
+        }
+        1 => settings.leading_zero = !settings.leading_zero,
+        _ => settings.transitions = !settings.transitions,
+    }
+
+    SETTINGS_CHANGED.signal(());
     SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
 }
 
 async fn adjust_brightness() {
-    let mut brightness = CURRENT_BRIGHTNESS.lock().await;
-    *brightness = (*brightness + 1) % 6;
+    let mut settings = SETTINGS.lock().await;
+    settings.brightness = (settings.brightness + 1) % MAX_BRIGHTNESS_LEVEL as u8;
+    SETTINGS_CHANGED.signal(());
     SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Normal);
 }
 
+/// Where to go once the alarm stops ringing, a countdown that ran out
+/// meanwhile still raises its alert.
+async fn after_ringing() -> State {
+    if COUNTDOWN
+        .lock()
+        .await
+        .is_expired(Instant::now().as_millis())
+    {
+        State::CountdownAlert
+    } else {
+        State::Idle
+    }
+}
+
 async fn to_state(new_state: State) {
     let state = { CURRENT_STATE.lock().await.clone() };
     info!("State change: {} -> {}", state, new_state);
@@ -244,11 +953,15 @@ async fn blink_task() {
 
     loop {
         let state = { CURRENT_STATE.lock().await.clone() };
-        if let State::SettingTime(digit) = state {
-            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Blink(BlinkData {
-                position: digit as usize,
-                frame: blink_frame,
-            }));
+        if let State::SettingTime(_)
+        | State::SettingDate(_)
+        | State::SettingAlarm(..)
+        | State::SettingCountdown(_)
+        | State::Settings(_)
+        | State::Ringing
+        | State::CountdownAlert = state
+        {
+            SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::Blink(BlinkData { frame: blink_frame }));
             blink_frame = (blink_frame + 1) % 2;
         } else {
             blink_frame = 0;
@@ -257,56 +970,154 @@ async fn blink_task() {
     }
 }
 
+/// Refreshes the countdown and the stopwatch whenever the shown digits
+/// change and raises the alert when the countdown runs out.
 #[embassy_executor::task]
-async fn button1_task(button_pin1: AnyPin) {
-    let mut button = Input::new(button_pin1, Pull::Up);
+async fn timer_task() {
+    let mut shown_seconds = None;
+    let mut shown_stopwatch = None;
 
     loop {
-        wait_for_low_debounced(&mut button).await;
-        // info!("Button1 pressed!");
+        let now = Instant::now().as_millis();
+        let countdown = *COUNTDOWN.lock().await;
+        let stopwatch = *STOPWATCH.lock().await;
+        let state = { CURRENT_STATE.lock().await.clone() };
 
-        let a = select(button.wait_for_high(), Timer::after(Duration::from_secs(3))).await;
+        if countdown.is_running() {
+            if countdown.is_expired(now) {
+                // stops it at zero
+                COUNTDOWN.lock().await.pause(now);
+                send_event(Event::CountdownExpired).await;
+            } else {
+                let seconds = countdown.remaining_seconds(now);
+                if shown_seconds != Some(seconds) && matches!(state, State::Countdown) {
+                    SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
+                }
+                shown_seconds = Some(seconds);
+            }
+        }
 
-        match a {
-            Either::First(_) => {
-                send_event(Event::SetButton(ButtonPress::Short)).await;
+        if stopwatch.is_running() {
+            let numbers = stopwatch_numbers(stopwatch.elapsed(now));
+            if shown_stopwatch != Some(numbers) && matches!(state, State::Stopwatch) {
+                SCREEN_REFRESH_SIGNAL.signal(ScreenRefresh::TimeChanged);
             }
-            Either::Second(_) => {
-                send_event(Event::SetButton(ButtonPress::Long)).await;
-                button.wait_for_high().await;
+            shown_stopwatch = Some(numbers);
+        }
+
+        Timer::after(Duration::from_millis(TIMER_POLL_MS)).await;
+    }
+}
+
+/// Polls the alarm flag of the PCF8563, the interrupt pin is not wired.
+#[embassy_executor::task]
+async fn alarm_task(rtc: &'static StaticRtc) {
+    let mut ticker = Ticker::every(Duration::from_secs(1));
+    loop {
+        let ringing = {
+            let mut rtc = rtc.lock().await;
+            let ringing = rtc.get_alarm_flag().unwrap();
+            if ringing {
+                rtc.clear_alarm_flag().unwrap();
             }
+            ringing
+        };
+
+        if ringing {
+            // a snooze rings only once
+            *SNOOZE.lock().await = None;
+            send_event(Event::AlarmRinging).await;
         }
+        ticker.next().await;
     }
 }
 
+/// Recognises gestures on both buttons, which pull their pins low.
 #[embassy_executor::task]
-async fn button2_task(button_pin: AnyPin) {
-    let mut button = Input::new(button_pin, Pull::Up);
+async fn buttons_task(set_pin: AnyPin, adjust_pin: AnyPin) {
+    let mut buttons = [
+        Input::new(set_pin, Pull::Up),
+        Input::new(adjust_pin, Pull::Up),
+    ];
+    let mut recognizer = Recognizer::new(GESTURE_TIMING, BUTTON_OPTIONS);
 
     loop {
-        wait_for_low_debounced(&mut button).await;
-        // info!("Button2 pressed!");
+        // only setting the time takes repeats, elsewhere holding adjust
+        // is a short press until it becomes a long one
+        let setting_time = matches!(*CURRENT_STATE.lock().await, State::SettingTime(_));
+        recognizer.set_options(
+            ADJUST_BUTTON,
+            gesture::Options {
+                repeat: setting_time,
+                ..BUTTON_OPTIONS[ADJUST_BUTTON]
+            },
+        );
 
-        let a = select(button.wait_for_high(), Timer::after(Duration::from_secs(3))).await;
+        let now = Instant::now().as_millis();
+        let pressed = [
+            buttons[SET_BUTTON].is_low(),
+            buttons[ADJUST_BUTTON].is_low(),
+        ];
+        while let Some(gesture) = recognizer.update(pressed, now) {
+            send_event(gesture_event(gesture)).await;
+        }
 
-        match a {
-            Either::First(_) => {
-                send_event(Event::AdjustButton(ButtonPress::Short)).await;
+        // until a level differs from the one just read, or a timeout
+        let [set, adjust] = &mut buttons;
+        let change = select(
+            wait_for_change(set, pressed[SET_BUTTON]),
+            wait_for_change(adjust, pressed[ADJUST_BUTTON]),
+        );
+        match recognizer.deadline() {
+            Some(deadline) => {
+                select(change, Timer::at(Instant::from_millis(deadline))).await;
             }
-            Either::Second(_) => {
-                send_event(Event::AdjustButton(ButtonPress::Long)).await;
-                button.wait_for_high().await;
+            None => {
+                change.await;
             }
         }
     }
 }
 
+async fn wait_for_change(button: &mut Input<'_, AnyPin>, pressed: bool) {
+    if pressed {
+        button.wait_for_high().await;
+    } else {
+        button.wait_for_low().await;
+    }
+}
+
+fn gesture_event(gesture: Gesture) -> Event {
+    let (button, press) = match gesture {
+        Gesture::Short(button) => (button, ButtonPress::Short),
+        Gesture::Double(button) => (button, ButtonPress::Double),
+        Gesture::Long(button) => (button, ButtonPress::Long),
+        Gesture::Repeat(button) => (button, ButtonPress::Repeat),
+        Gesture::Both => return Event::BothButtons,
+    };
+
+    if button == SET_BUTTON {
+        Event::SetButton(press)
+    } else {
+        Event::AdjustButton(press)
+    }
+}
+
 #[embassy_executor::task]
 async fn process_events(rtc: &'static StaticRtc) {
     loop {
         let event = EVENT_CHANNEL.recv().await;
         info!("Event: {}", event);
-        on_event(event, rtc).await;
+
+        // only the time view takes double clicks, elsewhere they are two
+        // clicks
+        let idle = matches!(*CURRENT_STATE.lock().await, State::Idle);
+        if let (Event::SetButton(ButtonPress::Double), false) = (&event, idle) {
+            on_event(Event::SetButton(ButtonPress::Short), rtc).await;
+            on_event(Event::SetButton(ButtonPress::Short), rtc).await;
+        } else {
+            on_event(event, rtc).await;
+        }
     }
 }
 
@@ -314,29 +1125,11 @@ async fn process_events(rtc: &'static StaticRtc) {
 async fn run_time() {
     let mut ticker = Ticker::every(Duration::from_secs(1));
     loop {
-        {
-            let mut time = CURRENT_TIME.lock().await;
-            time.seconds += 1;
-
-            if time.seconds >= 60 {
-                time.seconds = 0;
-                time.minutes += 1;
-            }
-
-            if time.minutes >= 60 {
-                time.minutes = 0;
-                time.hours += 1;
-            }
-
-            if time.hours >= 24 {
-                time.hours = 0;
-                time.day += 1;
-            }
-
-            // ignoring calendar for now
+        CURRENT_TIME.lock().await.tick();
+        // seconds start over when the RTC is started at a set time
+        while let Either::Second(_) = select(ticker.next(), TIME_STARTED.wait()).await {
+            ticker = Ticker::every(Duration::from_secs(1));
         }
-
-        ticker.next().await;
     }
 }
 
@@ -347,7 +1140,18 @@ async fn send_event(event: Event) {
 #[derive(Clone, Format)]
 enum State {
     Idle,
-    SettingTime(u8), //position: minutes, hours
+    ShowingDate(u8),      //page: day and month, year, weekday
+    SettingTime(u8),      //position: minutes, hours
+    SettingDate(u8),      //position: year, month, day
+    SettingAlarm(u8, u8), //alarm, position: enabled, hours, minutes, Sunday to Saturday
+    Ringing,
+    Countdown,
+    SettingCountdown(u8), //position: minutes, seconds
+    CountdownAlert,
+    Stopwatch,
+    ReviewingLaps(u8), //lap, newest first
+    Settings(u8),      //position: hour format, leading zero, transitions
+    ShowingText,       //sent over the console
 }
 
 #[derive(Clone, Format)]
@@ -355,32 +1159,327 @@ enum ScreenRefresh {
     TimeChanged,
     Blink(BlinkData),
     Normal,
+    SelfTest,
 }
 
 #[derive(Clone, Format)]
 struct BlinkData {
-    position: usize,
     frame: u8,
 }
 
-const fn default_datetime() -> DateTime {
-    DateTime {
-        year: 0,
-        month: 1,
-        weekday: 0,
-        day: 1,
-        hours: 0,
-        minutes: 0,
-        seconds: 0,
+/// What the display shows in a state, and which separator dots are lit.
+fn screen_content(
+    state: &State,
+    time: &calendar::DateTime,
+    countdown: &Countdown,
+    stopwatch: &Stopwatch,
+    settings: &Settings,
+    text: &[Symbol; 4],
+    now: u64,
+) -> ([Symbol; 4], Separator) {
+    let alarms = &settings.alarms;
+    let blank = Symbol::from_mask([0; 6]);
+    // shown for enabled settings, a dash for disabled ones
+    let enabled = |on: bool| {
+        if on {
+            Icon::Check.symbol()
+        } else {
+            seven_segment::to_symbol(seven_segment::G)
+        }
+    };
+
+    match state {
+        State::SettingAlarm(alarm, 0) => {
+            let alarm_index = *alarm as usize;
+            (
+                [
+                    Icon::Bell.symbol(),
+                    DIGITS[alarm_index + 1],
+                    blank,
+                    enabled(alarms[alarm_index].enabled),
+                ],
+                Separator::Off,
+            )
+        }
+        State::SettingAlarm(alarm, 1..=2) => {
+            let alarm = &alarms[*alarm as usize];
+            (
+                clock_time(alarm.hours, alarm.minutes, settings),
+                Separator::Colon,
+            )
+        }
+        State::SettingAlarm(alarm, position) => {
+            let weekday = (*position - 3) as usize;
+            let alarm = &alarms[*alarm as usize];
+            (
+                [
+                    WEEKDAYS[weekday * 2],
+                    WEEKDAYS[weekday * 2 + 1],
+                    blank,
+                    enabled(alarm.weekdays & (1 << weekday) != 0),
+                ],
+                Separator::Off,
+            )
+        }
+        State::Countdown => {
+            let seconds = countdown.remaining_seconds(now);
+            if countdown.is_started() && seconds < 60 {
+                (progress_bar(seconds, 60), Separator::Off)
+            } else {
+                (
+                    two_numbers((seconds / 60) as u8, (seconds % 60) as u8),
+                    Separator::Colon,
+                )
+            }
+        }
+        State::SettingCountdown(_) => {
+            let seconds = countdown.duration() / 1000;
+            (
+                two_numbers((seconds / 60) as u8, (seconds % 60) as u8),
+                Separator::Colon,
+            )
+        }
+        State::CountdownAlert => (two_numbers(0, 0), Separator::Colon),
+        State::Stopwatch => {
+            let (left, right) = stopwatch_numbers(stopwatch.elapsed(now));
+            (two_numbers(left, right), Separator::Colon)
+        }
+        State::ReviewingLaps(lap) => {
+            // the upper dot tells a lap from the running time
+            let laps = stopwatch.laps();
+            let lap_time = laps[laps.len() - 1 - (*lap as usize % laps.len())];
+            let (left, right) = stopwatch_numbers(lap_time);
+            (two_numbers(left, right), Separator::UpperDot)
+        }
+        State::Idle | State::SettingTime(_) | State::Ringing => (
+            clock_time(time.hours, time.minutes, settings),
+            Separator::Colon,
+        ),
+        State::Settings(0) => {
+            let (tens, ones) = match settings.hour_format {
+                HourFormat::TwentyFour => (2, 4),
+                HourFormat::Twelve => (1, 2),
+            };
+            (
+                [DIGITS[tens], DIGITS[ones], blank, CH_LTR[1]],
+                Separator::Off,
+            )
+        }
+        State::Settings(1) => (
+            [DIGITS[0], blank, blank, enabled(settings.leading_zero)],
+            Separator::Off,
+        ),
+        State::Settings(_) => (
+            [
+                seven_segment::to_symbol(seven_segment::from_char('t').unwrap()),
+                blank,
+                blank,
+                enabled(settings.transitions),
+            ],
+            Separator::Off,
+        ),
+        State::ShowingText => (*text, Separator::Off),
+        State::ShowingDate(1) | State::SettingDate(0) => {
+            let year = time.year as usize;
+            let digits = [year / 1000 % 10, year / 100 % 10, year / 10 % 10, year % 10];
+            (digits.map(|digit| DIGITS[digit]), Separator::Off)
+        }
+        State::ShowingDate(2) => {
+            let weekday = time.weekday as usize;
+            (
+                [
+                    WEEKDAYS[weekday * 2],
+                    WEEKDAYS[weekday * 2 + 1],
+                    blank,
+                    blank,
+                ],
+                Separator::Off,
+            )
+        }
+        State::ShowingDate(_) | State::SettingDate(_) => {
+            (two_numbers(time.day, time.month), Separator::LowerDot)
+        }
+    }
+}
+
+/// Minutes and seconds below an hour, hours and minutes above.
+fn stopwatch_numbers(elapsed_ms: u64) -> (u8, u8) {
+    let seconds = elapsed_ms / 1000;
+    if seconds < 60 * 60 {
+        ((seconds / 60) as u8, (seconds % 60) as u8)
+    } else {
+        ((seconds / 3600 % 100) as u8, (seconds / 60 % 60) as u8)
+    }
+}
+
+fn two_numbers(left: u8, right: u8) -> [Symbol; 4] {
+    [left / 10, left % 10, right / 10, right % 10].map(|digit| DIGITS[digit as usize])
+}
+
+/// Hours and minutes in the chosen format, the decimal point of the last
+/// cell marks the afternoon on a 12-hour clock.
+fn clock_time(hours: u8, minutes: u8, settings: &Settings) -> [Symbol; 4] {
+    let (hours, pm) = settings.hour_format.convert(hours);
+    let mut symbols = two_numbers(hours, minutes);
+
+    if hours < 10 && !settings.leading_zero {
+        symbols[0] = Symbol::from_mask([0; 6]);
+    }
+    if pm {
+        let mut mask = symbols[3].mask();
+        let dot = seven_segment::to_symbol(seven_segment::DP).mask();
+        for (byte, dot) in mask.iter_mut().zip(dot) {
+            *byte |= dot;
+        }
+        symbols[3] = Symbol::from_mask(mask);
+    }
+    symbols
+}
+
+/// The cells dimmed while blinking, those of the value being set.
+fn blinking_cells(state: &State) -> core::ops::Range<usize> {
+    match state {
+        State::SettingTime(0) | State::SettingDate(1) => 2..4,
+        State::SettingTime(1) | State::SettingDate(2) => 0..2,
+        State::SettingDate(0) | State::Ringing | State::CountdownAlert => 0..4,
+        State::SettingCountdown(0) => 0..2,
+        State::SettingCountdown(_) => 2..4,
+        State::SettingAlarm(_, 1) => 0..2,
+        State::SettingAlarm(_, 2) => 2..4,
+        State::SettingAlarm(..) => 3..4,
+        State::Settings(0) => 0..2,
+        State::Settings(_) => 3..4,
+        _ => 0..0,
+    }
+}
+
+/// Reads the date and time, the century comes from the PCF8563 century flag
+/// and the weekday is derived from the date.
+fn read_rtc(rtc: &mut Rtc) -> calendar::DateTime {
+    let time = rtc.get_datetime().unwrap();
+    let century = rtc.get_century_flag().unwrap();
+
+    let mut time = calendar::DateTime::new(
+        calendar::from_rtc_year(time.year, century),
+        time.month,
+        time.day,
+        time.hours % 24,
+        time.minutes % 60,
+        time.seconds % 60,
+    );
+    // the registers hold garbage after the backup supply ran out
+    time.normalize();
+    time
+}
+
+fn write_rtc(rtc: &mut Rtc, time: &calendar::DateTime) {
+    let (year, century) = calendar::to_rtc_year(time.year);
+
+    rtc.set_datetime(&DateTime {
+        year,
+        month: time.month,
+        weekday: time.weekday,
+        day: time.day,
+        hours: time.hours,
+        minutes: time.minutes,
+        seconds: time.seconds,
+    })
+    .unwrap();
+
+    if century {
+        rtc.set_century_flag().unwrap();
+    } else {
+        rtc.clear_century_flag().unwrap();
     }
 }
 
 static CURRENT_STATE: Mutex<CriticalSectionRawMutex, State> = Mutex::new(State::Idle);
-static CURRENT_TIME: Mutex<ThreadModeRawMutex, DateTime> = Mutex::new(default_datetime());
+static SNOOZE: Mutex<ThreadModeRawMutex, Option<AlarmTime>> = Mutex::new(None);
+static COUNTDOWN: Mutex<ThreadModeRawMutex, Countdown> =
+    Mutex::new(Countdown::new(DEFAULT_COUNTDOWN_MS));
+static STOPWATCH: Mutex<ThreadModeRawMutex, Stopwatch> = Mutex::new(Stopwatch::new());
+static SETTINGS: Mutex<ThreadModeRawMutex, Settings> = Mutex::new(DEFAULT_SETTINGS);
+static SETTINGS_CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+static TIME_STARTED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+static CONSOLE_TEXT: Mutex<ThreadModeRawMutex, [Symbol; 4]> =
+    Mutex::new([Symbol::from_mask([0; 6]); 4]);
+static CURRENT_TIME: Mutex<ThreadModeRawMutex, calendar::DateTime> =
+    Mutex::new(calendar::DateTime::DEFAULT);
+
+// set to `Orientation::Rotated180` for enclosures with the board upside down,
+// once settings are stored the console `orientation` command changes it
+const DISPLAY_ORIENTATION: Orientation = Orientation::Normal;
+// used until settings were stored
+const DEFAULT_SETTINGS: Settings = Settings {
+    orientation: DISPLAY_ORIENTATION,
+    ..Settings::DEFAULT
+};
+
+// the settings live in the last sectors of the flash, memory.x keeps the
+// program out of them
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+const SETTINGS_SECTORS: usize = 2;
+const SETTINGS_OFFSET: u32 = (FLASH_SIZE - SETTINGS_SECTORS * ERASE_SIZE) as u32;
+// quiet time after the last change before the settings are written
+const SETTINGS_SAVE_DELAY_MS: u64 = 5000;
+
+// the test IDs of the embassy examples, not registered to anyone
+const USB_VID: u16 = 0xc0de;
+const USB_PID: u16 = 0xcafe;
+const USB_PACKET_SIZE: u16 = 64;
+const CONSOLE_LINE_LEN: usize = 64;
+// fits the help text
+const CONSOLE_REPLY_LEN: usize = 1024;
+const _: () = assert!(console::HELP.len() <= CONSOLE_REPLY_LEN);
+// the PCF8563 counts its first second 0.508 s after the STOP bit is cleared,
+// see the STOP bit description in its datasheet
+const RTC_FIRST_SECOND_MS: u64 = 508;
+const SELF_TEST_STEP_MS: u64 = 150;
+const SELF_TEST_HOLD_MS: u64 = 2000;
+
+// indices of the buttons in the gesture recogniser
+const SET_BUTTON: usize = 0;
+const ADJUST_BUTTON: usize = 1;
+const GESTURE_TIMING: gesture::Timing = gesture::Timing::DEFAULT;
+// set pages through views and can wait for a double click, adjust repeats
+// when held while setting the time, see buttons_task
+const BUTTON_OPTIONS: [gesture::Options; gesture::BUTTONS] = [
+    gesture::Options {
+        double_click: true,
+        repeat: false,
+    },
+    gesture::Options {
+        double_click: false,
+        repeat: false,
+    },
+];
+
+// transition style used when the digit in each position changes
+const DIGIT_TRANSITIONS: [Style; 4] = [
+    Style::Crossfade,
+    Style::Crossfade,
+    Style::Wipe(Direction::TopToBottom),
+    Style::Morph,
+];
+const TRANSITION_FRAMES: u8 = 12;
+// day and month, year, weekday
+const DATE_PAGES: u8 = 3;
+// enabled, hours, minutes and the seven weekdays
+const ALARM_POSITIONS: u8 = 10;
+// hour format, leading zero and digit transitions
+const SETTINGS_POSITIONS: u8 = 3;
+const SNOOZE_MINUTES: u16 = 9;
+const DEFAULT_COUNTDOWN_MS: u32 = 5 * 60 * 1000;
+const COUNTDOWN_SECONDS_STEP: u32 = 5;
+const TIMER_POLL_MS: u64 = 100;
+const TRANSITION_FRAME_MS: u64 = 40;
+// encoded from boot.txt with the animation example of clocklib
+const BOOT_ANIMATION: &[u8] = include_bytes!("../animations/boot.bin");
+// half the period of the colon pulse
+const COLON_PULSE_MS: u64 = 500;
 
 const MAX_BRIGHTNESS_LEVEL: usize = 6;
 const BRIGHTNESS_MAP: [u8; MAX_BRIGHTNESS_LEVEL] = [0x05, 0x10, 0x20, 0x40, 0x60, 0x90];
-static CURRENT_BRIGHTNESS: Mutex<ThreadModeRawMutex, usize> = Mutex::new(4); // 0 - 6
 
 static EVENT_CHANNEL: Channel<ThreadModeRawMutex, Event, 10> = Channel::new();
 static SCREEN_REFRESH_SIGNAL: Signal<ThreadModeRawMutex, ScreenRefresh> = Signal::new();
@@ -391,6 +1490,13 @@ async fn main(spawner: Spawner) {
 
     let p = embassy_rp::init(Default::default());
 
+    let mut flash = StaticFlash::new(p.FLASH);
+    let mut journal = Journal::new(SETTINGS_OFFSET, SETTINGS_SECTORS, ERASE_SIZE);
+    let stored = journal.load(&mut flash).unwrap();
+    let mut settings = stored.unwrap_or(DEFAULT_SETTINGS);
+    settings.brightness = settings.brightness.min(MAX_BRIGHTNESS_LEVEL as u8 - 1);
+    *SETTINGS.lock().await = settings;
+
     let mut config = Config::default();
     config.frequency = 400_000;
     let sda = p.PIN_28;
@@ -400,8 +1506,37 @@ async fn main(spawner: Spawner) {
 
     let leds1 = IS31FL3731::new(shared_i2c.acquire_i2c(), 0x76);
     let leds2 = IS31FL3731::new(shared_i2c.acquire_i2c(), 0x74);
+    let mut usb_config = embassy_usb::Config::new(USB_VID, USB_PID);
+    usb_config.manufacturer = Some("mosaic");
+    usb_config.product = Some("Mosaic clock console");
+    usb_config.max_power = 100;
+    usb_config.max_packet_size_0 = USB_PACKET_SIZE as u8;
+    // a composite device with an interface association, for Windows
+    usb_config.device_class = 0xEF;
+    usb_config.device_sub_class = 0x02;
+    usb_config.device_protocol = 0x01;
+    usb_config.composite_with_iads = true;
+
+    let mut usb_builder = embassy_usb::Builder::new(
+        Driver::new(p.USB, Irqs),
+        usb_config,
+        make_static!([0u8; 256]),
+        make_static!([0u8; 256]),
+        make_static!([0u8; 256]),
+        make_static!([0u8; 64]),
+    );
+    let console_class = CdcAcmClass::new(
+        &mut usb_builder,
+        make_static!(cdc_acm::State::new()),
+        USB_PACKET_SIZE,
+    );
+    let usb = usb_builder.build();
+
     let mut clock = ClockDisplay::new([Some(leds1), Some(leds2), None]);
+    clock.orientation = settings.orientation;
     clock.setup().unwrap();
+
+    play_animation(&mut clock, BOOT_ANIMATION, 0x70).await;
     
     clock.draw_CH(0, 0, 0x70).unwrap();
     clock.draw_CH(1, 1, 0x70).unwrap();
@@ -427,8 +1562,12 @@ async fn main(spawner: Spawner) {
     unwrap!(spawner.spawn(screen_update(clock)));
     //unwrap!(spawner.spawn(led_numbers_test(clock)));
 
-    unwrap!(spawner.spawn(button1_task(p.PIN_2.degrade())));
-    unwrap!(spawner.spawn(button2_task(p.PIN_3.degrade())));
+    unwrap!(spawner.spawn(buttons_task(p.PIN_2.degrade(), p.PIN_3.degrade())));
     unwrap!(spawner.spawn(blink_task()));
+    unwrap!(spawner.spawn(alarm_task(rtc)));
+    unwrap!(spawner.spawn(timer_task()));
     unwrap!(spawner.spawn(process_events(rtc)));
+    unwrap!(spawner.spawn(settings_task(flash, journal)));
+    unwrap!(spawner.spawn(usb_task(usb)));
+    unwrap!(spawner.spawn(console_task(console_class, rtc)));
 }
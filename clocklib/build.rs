@@ -0,0 +1,485 @@
+//! Generates the segment map of a mosaic cell.
+//!
+//! The LED assignment is read from `segments.txt` and the shape of every
+//! segment from the editor drawing in `clocklib-editor/leds.svg`. The result
+//! is written to `$OUT_DIR/segments.rs`, which `lib.rs` includes as
+//! `SEGMENTS`, along with the LEDs of the separator dots as
+//! `SEPARATOR_DOTS`.
+//!
+//! Outlines, centroids and bounds are stored in tenths of a millimetre,
+//! relative to the centre of the cell, with y pointing down like in the
+//! drawing. Areas are in square tenths of a millimetre.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+const LAYOUT: &str = "segments.txt";
+const DRAWING: &str = "../clocklib-editor/leds.svg";
+
+// the drawing is in points
+const UNITS_PER_POINT: f64 = 254.0 / 72.0;
+
+// curves with a chord shorter than this (in points) are the rounded corners
+// of the LED pads and are replaced by a straight line
+const MIN_CURVE_CHORD: f64 = 2.0;
+const CURVE_STEPS: usize = 8;
+
+// centroids closer than this vertically (in points) are in the same row
+const ROW_TOLERANCE: f64 = 4.0;
+
+type Matrix = [f64; 6];
+// segment id and its LEDs
+type Assignment = Vec<(usize, Vec<u8>)>;
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", LAYOUT);
+    println!("cargo:rerun-if-changed={}", DRAWING);
+
+    let layout = fs::read_to_string(LAYOUT).expect("failed to read the segment layout");
+    let drawing = fs::read_to_string(DRAWING).expect("failed to read the segment drawing");
+
+    let (assignment, dots) = parse_layout(&layout);
+    assert_eq!(
+        dots.len(),
+        2,
+        "{} must list the upper and the lower separator dot",
+        LAYOUT
+    );
+    let shapes = parse_drawing(&drawing);
+
+    let first = |cell: u8| {
+        let outline = shapes
+            .get(&(cell, 1))
+            .unwrap_or_else(|| panic!("segment s{}-1 is missing in {}", cell, DRAWING));
+        outline.iter().map(|p| p.0).fold(f64::MAX, f64::min)
+    };
+    let pitch = first(2) - first(1);
+
+    // the outer group of the drawing places the centre of the first cell at
+    // its origin, so the outlines of `s1-*` are already cell relative
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// generated by build.rs from {} and {}",
+        LAYOUT, DRAWING
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// Distance between the centres of two neighbouring cells."
+    )
+    .unwrap();
+    writeln!(out, "pub const CELL_PITCH: i16 = {};", to_units(pitch)).unwrap();
+    writeln!(out).unwrap();
+    let mut cell_min = (f64::MAX, f64::MAX);
+    let mut cell_max = (f64::MIN, f64::MIN);
+    let mut segments = String::new();
+    let mut centroids = Vec::new();
+
+    for (i, (id, leds)) in assignment.iter().enumerate() {
+        assert_eq!(*id, i + 1, "segments in {} must be listed in order", LAYOUT);
+
+        let outline = shapes
+            .get(&(1, *id))
+            .unwrap_or_else(|| panic!("segment s1-{} is missing in {}", id, DRAWING));
+
+        let mut points: Vec<(i16, i16)> = Vec::new();
+        for &(x, y) in outline {
+            let point = (to_units(x), to_units(y));
+            if points.last() != Some(&point) && points.first() != Some(&point) {
+                points.push(point);
+            }
+        }
+
+        let (area, centroid) = area_and_centroid(outline);
+        let (min, max) = bounds(outline);
+        centroids.push(centroid);
+        cell_min = (cell_min.0.min(min.0), cell_min.1.min(min.1));
+        cell_max = (cell_max.0.max(max.0), cell_max.1.max(max.1));
+
+        writeln!(segments, "    Segment {{").unwrap();
+        writeln!(segments, "        leds: &{:?},", leds).unwrap();
+        write!(segments, "        outline: &[").unwrap();
+        for (x, y) in points {
+            write!(segments, "Point::new({}, {}), ", x, y).unwrap();
+        }
+        writeln!(segments, "],").unwrap();
+        writeln!(segments, "        centroid: {},", point(centroid)).unwrap();
+        writeln!(segments, "        bounds: {},", rect(min, max)).unwrap();
+        writeln!(
+            segments,
+            "        area: {},",
+            (area * UNITS_PER_POINT * UNITS_PER_POINT).round() as u16
+        )
+        .unwrap();
+        writeln!(segments, "    }},").unwrap();
+    }
+
+    writeln!(out, "/// The box around all segments of a cell.").unwrap();
+    writeln!(
+        out,
+        "pub const CELL_BOUNDS: Rect = {};",
+        rect(cell_min, cell_max)
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// Maps the segments of a cell to LEDs on one driver."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const SEGMENTS: [Segment; {}] = [",
+        assignment.len()
+    )
+    .unwrap();
+    write!(out, "{}", segments).unwrap();
+    writeln!(out, "];").unwrap();
+
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// The segment in the mirrored position of each segment, left to right."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const MIRROR_X: [usize; {}] = {:?};",
+        centroids.len(),
+        mirror_x(&centroids)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "/// The segment in the mirrored position of each segment, top to bottom."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const MIRROR_Y: [usize; {}] = {:?};",
+        centroids.len(),
+        mirror_y(&centroids)
+    )
+    .unwrap();
+
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// The upper and lower separator dot as driver and LED."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const SEPARATOR_DOTS: [(usize, u8); {}] = {:?};",
+        dots.len(),
+        dots
+    )
+    .unwrap();
+
+    let segment_count = shapes.keys().filter(|(cell, _)| *cell == 1).count();
+    assert_eq!(
+        segment_count,
+        assignment.len(),
+        "{} and {} disagree on the number of segments",
+        LAYOUT,
+        DRAWING
+    );
+
+    let path = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("segments.rs");
+    fs::write(path, out).unwrap();
+}
+
+fn to_units(points: f64) -> i16 {
+    (points * UNITS_PER_POINT).round() as i16
+}
+
+// the cell is symmetric left to right, so the mirror image of a centroid
+// lands on the centroid of the mirrored segment
+fn mirror_x(centroids: &[(f64, f64)]) -> Vec<usize> {
+    let mirror: Vec<usize> = centroids
+        .iter()
+        .map(|&(x, y)| {
+            let distance = |&(cx, cy): &(f64, f64)| (cx + x).powi(2) + (cy - y).powi(2);
+            (0..centroids.len())
+                .min_by(|&a, &b| distance(&centroids[a]).total_cmp(&distance(&centroids[b])))
+                .unwrap()
+        })
+        .collect();
+
+    assert_permutation(&mirror, "left to right");
+    mirror
+}
+
+// top and bottom of the cell have the same structure but different sizes, so
+// rows are mirrored instead, keeping the order within a row
+fn mirror_y(centroids: &[(f64, f64)]) -> Vec<usize> {
+    let mut rows: Vec<Vec<usize>> = Vec::new();
+    let mut by_y: Vec<usize> = (0..centroids.len()).collect();
+    by_y.sort_by(|&a, &b| centroids[a].1.total_cmp(&centroids[b].1));
+
+    for segment in by_y {
+        match rows.last_mut() {
+            Some(row) if centroids[segment].1 - centroids[row[0]].1 < ROW_TOLERANCE => {
+                row.push(segment)
+            }
+            _ => rows.push(vec![segment]),
+        }
+    }
+    for row in rows.iter_mut() {
+        row.sort_by(|&a, &b| centroids[a].0.total_cmp(&centroids[b].0));
+    }
+
+    let mut mirror = vec![0; centroids.len()];
+    for (row, mirrored) in rows.iter().zip(rows.iter().rev()) {
+        assert_eq!(
+            row.len(),
+            mirrored.len(),
+            "the segment rows of the cell are not symmetric top to bottom"
+        );
+        for (&segment, &other) in row.iter().zip(mirrored.iter()) {
+            mirror[segment] = other;
+        }
+    }
+
+    assert_permutation(&mirror, "top to bottom");
+    mirror
+}
+
+fn assert_permutation(mirror: &[usize], direction: &str) {
+    let mut sorted = mirror.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(
+        sorted.len(),
+        mirror.len(),
+        "the cell can not be mirrored {}",
+        direction
+    );
+}
+
+fn point((x, y): (f64, f64)) -> String {
+    format!("Point::new({}, {})", to_units(x), to_units(y))
+}
+
+fn rect(min: (f64, f64), max: (f64, f64)) -> String {
+    format!("Rect::new({}, {})", point(min), point(max))
+}
+
+fn bounds(outline: &[(f64, f64)]) -> ((f64, f64), (f64, f64)) {
+    outline.iter().fold(
+        ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+        |(min, max), &(x, y)| ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y))),
+    )
+}
+
+// shoelace formula over the flattened outline
+fn area_and_centroid(outline: &[(f64, f64)]) -> (f64, (f64, f64)) {
+    let mut area = 0.0;
+    let mut centroid = (0.0, 0.0);
+
+    for (i, &(x0, y0)) in outline.iter().enumerate() {
+        let (x1, y1) = outline[(i + 1) % outline.len()];
+        let cross = x0 * y1 - x1 * y0;
+        area += cross;
+        centroid.0 += (x0 + x1) * cross;
+        centroid.1 += (y0 + y1) * cross;
+    }
+
+    area /= 2.0;
+    (
+        area.abs(),
+        (centroid.0 / (6.0 * area), centroid.1 / (6.0 * area)),
+    )
+}
+
+/// Returns the LEDs of every segment and the separator dots.
+fn parse_layout(layout: &str) -> (Assignment, Vec<(usize, u8)>) {
+    let mut segments = Vec::new();
+    let mut dots = Vec::new();
+
+    for (line_no, line) in layout.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (is_dot, line) = match line.strip_prefix("dot ") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let mut fields = line.split_whitespace().map(|field| {
+            field.parse::<usize>().unwrap_or_else(|_| {
+                panic!("{}:{}: invalid number `{}`", LAYOUT, line_no + 1, field)
+            })
+        });
+
+        let id = fields.next().unwrap();
+        if is_dot {
+            // the driver in place of the id
+            let led = match (fields.next(), fields.next()) {
+                (Some(led), None) => led,
+                _ => panic!("{}:{}: a dot is one driver and LED", LAYOUT, line_no + 1),
+            };
+            let led = u8::try_from(led)
+                .unwrap_or_else(|_| panic!("{}:{}: LED {} out of range", LAYOUT, line_no + 1, led));
+            dots.push((id, led));
+            continue;
+        }
+
+        let leds: Vec<u8> = fields
+            .map(|led| {
+                u8::try_from(led).unwrap_or_else(|_| {
+                    panic!("{}:{}: LED {} out of range", LAYOUT, line_no + 1, led)
+                })
+            })
+            .collect();
+        assert!(
+            !leds.is_empty(),
+            "{}:{}: segment {} has no LEDs",
+            LAYOUT,
+            line_no + 1,
+            id
+        );
+
+        segments.push((id, leds));
+    }
+
+    (segments, dots)
+}
+
+/// Returns the flattened outline of every `s<cell>-<id>` segment in the
+/// drawing, keyed by `(cell, id)`.
+fn parse_drawing(drawing: &str) -> BTreeMap<(u8, usize), Vec<(f64, f64)>> {
+    let mut shapes = BTreeMap::new();
+    let mut rest = drawing;
+
+    while let Some(start) = rest.find("<g id=\"s") {
+        rest = &rest[start + "<g id=\"s".len()..];
+
+        let id = &rest[..rest.find('"').unwrap()];
+        let (cell, segment) = id.split_once('-').expect("malformed segment id");
+        let key = (
+            cell.parse::<u8>().expect("malformed segment id"),
+            segment.parse::<usize>().expect("malformed segment id"),
+        );
+
+        let group = &rest[..rest.find('>').unwrap()];
+        let matrix = attribute(group, "transform")
+            .map(parse_matrix)
+            .unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+        let path = &rest[rest.find("<path").expect("segment without a path")..];
+        let data = attribute(path, "d").expect("path without data");
+
+        let outline = parse_path(data)
+            .into_iter()
+            .map(|(x, y)| {
+                (
+                    matrix[0] * x + matrix[2] * y + matrix[4],
+                    matrix[1] * x + matrix[3] * y + matrix[5],
+                )
+            })
+            .collect();
+
+        shapes.insert(key, outline);
+    }
+
+    shapes
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+fn parse_matrix(transform: &str) -> Matrix {
+    let values = transform
+        .strip_prefix("matrix(")
+        .and_then(|t| t.strip_suffix(')'))
+        .unwrap_or_else(|| panic!("unsupported transform `{}`", transform));
+
+    let mut matrix = [0.0; 6];
+    for (i, value) in values.split(',').enumerate() {
+        matrix[i] = value.trim().parse().expect("malformed transform");
+    }
+    matrix
+}
+
+/// Flattens a path made of absolute `M`, `L`, `C` and `Z` commands, which is
+/// all the drawing uses.
+fn parse_path(data: &str) -> Vec<(f64, f64)> {
+    let mut spaced = String::new();
+    for c in data.chars() {
+        match c {
+            'M' | 'L' | 'C' | 'Z' => {
+                spaced.push(' ');
+                spaced.push(c);
+                spaced.push(' ');
+            }
+            '-' if !spaced.ends_with('e') => spaced.push_str(" -"),
+            ',' => spaced.push(' '),
+            _ => spaced.push(c),
+        }
+    }
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+
+    let mut points = Vec::new();
+    let mut current = (0.0, 0.0);
+    let mut command = ' ';
+    let mut i = 0;
+    let value = |i: usize| -> f64 { tokens[i].parse().expect("malformed path") };
+
+    while i < tokens.len() {
+        if let Some(c) = tokens[i].chars().next().filter(char::is_ascii_alphabetic) {
+            command = c;
+            i += 1;
+            continue;
+        }
+
+        match command {
+            'M' | 'L' => {
+                current = (value(i), value(i + 1));
+                points.push(current);
+                i += 2;
+            }
+            'C' => {
+                let c1 = (value(i), value(i + 1));
+                let c2 = (value(i + 2), value(i + 3));
+                let end = (value(i + 4), value(i + 5));
+                i += 6;
+
+                let chord = ((end.0 - current.0).powi(2) + (end.1 - current.1).powi(2)).sqrt();
+                let steps = if chord < MIN_CURVE_CHORD {
+                    1
+                } else {
+                    CURVE_STEPS
+                };
+                for step in 1..=steps {
+                    let t = step as f64 / steps as f64;
+                    let u = 1.0 - t;
+                    let bezier = |p0: f64, p1: f64, p2: f64, p3: f64| {
+                        u * u * u * p0
+                            + 3.0 * u * u * t * p1
+                            + 3.0 * u * t * t * p2
+                            + t * t * t * p3
+                    };
+                    points.push((
+                        bezier(current.0, c1.0, c2.0, end.0),
+                        bezier(current.1, c1.1, c2.1, end.1),
+                    ));
+                }
+                current = end;
+            }
+            _ => panic!("unsupported path command `{}`", command),
+        }
+    }
+
+    points
+}
@@ -0,0 +1,60 @@
+//! Animation encoder.
+//!
+//! Turns the text form of an animation, see `clocklib::animation::encode`,
+//! into the binary form played by the firmware, and prints every frame.
+//!
+//! `cargo run --features std --example animation -- boot.txt boot.bin`
+
+use clocklib::animation::{encode, Animation, Player};
+use clocklib::{CELLS, SEGMENTS};
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (input, output) = match args.as_slice() {
+        [input, output] => (input, output),
+        _ => fail("usage: animation <input.txt> <output.bin>"),
+    };
+
+    let source =
+        fs::read_to_string(input).unwrap_or_else(|error| fail(&format!("{}: {}", input, error)));
+    let data = encode(&source).unwrap_or_else(|error| fail(&format!("{}: {}", input, error)));
+    fs::write(output, &data).unwrap_or_else(|error| fail(&format!("{}: {}", output, error)));
+
+    let animation = Animation::new(&data).unwrap();
+    let mut player = Player::new(animation);
+    let mut frames = 0;
+    let mut total: u32 = 0;
+
+    // a looping animation is shown once
+    while let Some(duration) = player.step() {
+        frames += 1;
+        total += duration as u32;
+
+        print!("{:5} ms ", duration);
+        for levels in player.levels().iter().take(CELLS) {
+            let lit = levels.iter().filter(|&&level| level > 0).count();
+            print!(" {:2}/{}", lit, SEGMENTS.len());
+        }
+        println!();
+
+        if player.at_end() {
+            break;
+        }
+    }
+
+    println!(
+        "{} frames, {} ms{}, {} bytes",
+        frames,
+        total,
+        if animation.looping() { ", looping" } else { "" },
+        data.len()
+    );
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
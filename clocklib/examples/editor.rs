@@ -0,0 +1,434 @@
+//! Glyph editor.
+//!
+//! Serves a page on http://127.0.0.1:8044 that shows the glyph tables of
+//! clocklib on the real segment shapes. Segments are toggled by clicking
+//! them, and saving writes the tables back into `src/glyphs.rs` as `glyph!`
+//! art.
+//!
+//! With `--board` the edited glyph is also shown on the first cell of a
+//! display connected through an FT232H, like in the other examples.
+//!
+//! `cargo run --features std --example editor`
+
+use clocklib::glyphs::TABLES;
+use clocklib::{Symbol, SEGMENTS};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const ADDRESS: &str = "127.0.0.1:8044";
+const GLYPHS_SOURCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/glyphs.rs");
+
+fn main() {
+    let mut board = if env::args().any(|arg| arg == "--board") {
+        Some(board::Board::open())
+    } else {
+        None
+    };
+
+    // the tables compiled into clocklib are only the starting point, saved
+    // edits are kept here until the editor is restarted with a new build
+    let mut tables: Vec<(String, Vec<Symbol>)> = TABLES
+        .iter()
+        .map(|(name, glyphs)| (name.to_string(), glyphs.to_vec()))
+        .collect();
+
+    let listener = TcpListener::bind(ADDRESS).expect("failed to listen");
+    println!("Glyph editor running on http://{}", ADDRESS);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        if let Err(error) = handle(stream, &mut tables, board.as_mut()) {
+            println!("Request failed: {}", error);
+        }
+    }
+}
+
+fn handle(
+    mut stream: TcpStream,
+    tables: &mut Vec<(String, Vec<Symbol>)>,
+    board: Option<&mut board::Board>,
+) -> Result<(), String> {
+    let (method, path, body) = read_request(&mut stream).map_err(|e| e.to_string())?;
+
+    let (status, content_type, response) = match (method.as_str(), path.as_str()) {
+        ("GET", "/") => ("200 OK", "text/html", page()),
+        ("GET", "/tables") => ("200 OK", "text/plain", format_tables(tables)),
+        ("POST", "/tables") => match parse_tables(&body).and_then(|new| save(&new).map(|_| new)) {
+            Ok(new) => {
+                *tables = new;
+                ("200 OK", "text/plain", "saved".to_string())
+            }
+            Err(error) => ("400 Bad Request", "text/plain", error),
+        },
+        ("POST", "/preview") => match parse_mask(body.split_whitespace()) {
+            Some(symbol) => {
+                if let Some(board) = board {
+                    board.show(&symbol);
+                }
+                ("200 OK", "text/plain", String::new())
+            }
+            None => ("400 Bad Request", "text/plain", "invalid mask".to_string()),
+        },
+        _ => ("404 Not Found", "text/plain", "not found".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        response.len(),
+        response
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn read_request(stream: &mut TcpStream) -> std::io::Result<(String, String, String)> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok((method, path, String::from_utf8_lossy(&body).into_owned()))
+}
+
+// one glyph per line: `<table> <mask byte> ... <mask byte>`, in hex
+fn format_tables(tables: &[(String, Vec<Symbol>)]) -> String {
+    let mut out = String::new();
+    for (name, glyphs) in tables {
+        for glyph in glyphs {
+            write!(out, "{}", name).unwrap();
+            for byte in glyph.mask() {
+                write!(out, " {:02x}", byte).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+    }
+    out
+}
+
+fn parse_tables(text: &str) -> Result<Vec<(String, Vec<Symbol>)>, String> {
+    let mut tables: Vec<(String, Vec<Symbol>)> = Vec::new();
+
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut fields = line.split_whitespace();
+        let name = fields.next().unwrap();
+        let symbol = parse_mask(fields).ok_or_else(|| format!("invalid glyph `{}`", line))?;
+
+        match tables.iter_mut().find(|(table, _)| table == name) {
+            Some((_, glyphs)) => glyphs.push(symbol),
+            None => tables.push((name.to_string(), vec![symbol])),
+        }
+    }
+
+    Ok(tables)
+}
+
+fn parse_mask<'a>(bytes: impl Iterator<Item = &'a str>) -> Option<Symbol> {
+    let mut mask = [0; 6];
+    let mut count = 0;
+
+    for byte in bytes {
+        *mask.get_mut(count)? = u8::from_str_radix(byte, 16).ok()?;
+        count += 1;
+    }
+
+    let unused = mask[5] >> (SEGMENTS.len() - 40);
+    if count != mask.len() || unused != 0 {
+        return None;
+    }
+
+    Some(Symbol::from_mask(mask))
+}
+
+fn save(tables: &[(String, Vec<Symbol>)]) -> Result<(), String> {
+    let mut source = fs::read_to_string(GLYPHS_SOURCE).map_err(|e| e.to_string())?;
+
+    for (name, glyphs) in tables {
+        source = replace_table(&source, name, glyphs)?;
+    }
+
+    fs::write(GLYPHS_SOURCE, source).map_err(|e| e.to_string())?;
+    println!("Saved {}", GLYPHS_SOURCE);
+    Ok(())
+}
+
+/// Rewrites the body of the glyph table `name` in `source`, keeping the
+/// comments in front of each glyph.
+fn replace_table(source: &str, name: &str, glyphs: &[Symbol]) -> Result<String, String> {
+    let header = format!("const {}: [Symbol; ", name);
+    let start = source
+        .find(&header)
+        .ok_or_else(|| format!("table {} not found in {}", name, GLYPHS_SOURCE))?;
+    let start = start + source[start..].find('\n').unwrap() + 1;
+    let end = start
+        + source[start..]
+            .find("\n];")
+            .ok_or_else(|| format!("end of table {} not found", name))?
+        + 1;
+
+    let mut labels: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    let mut pending = Vec::new();
+    let mut index = 0;
+    for line in source[start..end].lines() {
+        let line = line.trim();
+        if line.starts_with("//") {
+            pending.push(line);
+        } else if line.starts_with("glyph!(") {
+            labels.insert(index, std::mem::take(&mut pending));
+            index += 1;
+        }
+    }
+
+    let mut body = String::new();
+    for (i, glyph) in glyphs.iter().enumerate() {
+        for label in labels.get(&i).into_iter().flatten() {
+            writeln!(body, "    {}", label).unwrap();
+        }
+        writeln!(body, "    glyph!(").unwrap();
+        glyph.write_art(&mut body, "        ").unwrap();
+        writeln!(body, "    ),").unwrap();
+    }
+
+    let line_start = source[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+    let declaration = &source[line_start..start];
+    let count_start = declaration.find(&header).unwrap() + header.len();
+    let count_end = count_start + declaration[count_start..].find(']').unwrap();
+    let declaration = format!(
+        "{}{}{}",
+        &declaration[..count_start],
+        glyphs.len(),
+        &declaration[count_end..]
+    );
+
+    Ok(format!(
+        "{}{}{}{}",
+        &source[..line_start],
+        declaration,
+        body,
+        &source[end..]
+    ))
+}
+
+fn page() -> String {
+    let mut cell = String::new();
+    for (i, segment) in SEGMENTS.iter().enumerate() {
+        let points: Vec<String> = segment
+            .outline
+            .iter()
+            .map(|p| format!("{},{}", p.x, p.y))
+            .collect();
+        writeln!(
+            cell,
+            r#"<polygon id="s{}" points="{}"><title>{}</title></polygon>"#,
+            i,
+            points.join(" "),
+            i
+        )
+        .unwrap();
+    }
+
+    PAGE.replace("{segments}", &cell)
+        .replace("{segment_count}", &SEGMENTS.len().to_string())
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<title>Glyph editor</title>
+<style>
+    body { background: black; color: #ccc; font-family: monospace; display: flex; gap: 2em; }
+    polygon { fill: rgb(20, 20, 20); stroke: black; stroke-width: 2; cursor: pointer; }
+    polygon.on { fill: rgb(255, 228, 6); }
+    button.current { background: rgb(255, 228, 6); }
+    #glyphs button { min-width: 3em; }
+</style>
+</head>
+<body>
+<svg viewBox="-210 -270 420 540" width="420">
+{segments}
+</svg>
+<div>
+    <p><select id="table"></select></p>
+    <p id="glyphs"></p>
+    <p>
+        <button id="clear">Clear</button>
+        <button id="add">Add glyph</button>
+        <button id="save">Save</button>
+    </p>
+    <pre id="mask"></pre>
+    <pre id="status"></pre>
+</div>
+<script>
+    const segmentCount = {segment_count};
+    let tables = [];
+    let table = 0;
+    let glyph = 0;
+
+    const current = () => tables[table].glyphs[glyph];
+    const lit = (mask, s) => (mask[s >> 3] >> (s & 7)) & 1;
+    const hex = (mask) => mask.map((b) => b.toString(16).padStart(2, "0")).join(" ");
+
+    function render() {
+        const mask = current();
+        for (let s = 0; s < segmentCount; s++) {
+            document.getElementById("s" + s).classList.toggle("on", lit(mask, s) == 1);
+        }
+
+        const select = document.getElementById("table");
+        select.innerHTML = "";
+        tables.forEach((t, i) => select.add(new Option(t.name, i, false, i == table)));
+
+        const glyphs = document.getElementById("glyphs");
+        glyphs.innerHTML = "";
+        tables[table].glyphs.forEach((_, i) => {
+            const button = document.createElement("button");
+            button.textContent = i;
+            button.classList.toggle("current", i == glyph);
+            button.onclick = () => { glyph = i; render(); preview(); };
+            glyphs.append(button);
+        });
+
+        document.getElementById("mask").textContent =
+            "[" + mask.map((b) => "0x" + b.toString(16)).join(", ") + "]";
+    }
+
+    function preview() {
+        fetch("/preview", { method: "POST", body: hex(current()) });
+    }
+
+    for (let s = 0; s < segmentCount; s++) {
+        document.getElementById("s" + s).onclick = () => {
+            current()[s >> 3] ^= 1 << (s & 7);
+            render();
+            preview();
+        };
+    }
+
+    document.getElementById("table").onchange = (e) => {
+        table = Number(e.target.value);
+        glyph = 0;
+        render();
+        preview();
+    };
+
+    document.getElementById("clear").onclick = () => {
+        current().fill(0);
+        render();
+        preview();
+    };
+
+    document.getElementById("add").onclick = () => {
+        tables[table].glyphs.push([0, 0, 0, 0, 0, 0]);
+        glyph = tables[table].glyphs.length - 1;
+        render();
+        preview();
+    };
+
+    document.getElementById("save").onclick = async () => {
+        const body = tables
+            .flatMap((t) => t.glyphs.map((g) => t.name + " " + hex(g)))
+            .join("\n");
+        const response = await fetch("/tables", { method: "POST", body });
+        document.getElementById("status").textContent = await response.text();
+    };
+
+    fetch("/tables").then((r) => r.text()).then((text) => {
+        for (const line of text.split("\n").filter((l) => l.trim())) {
+            const [name, ...bytes] = line.trim().split(/\s+/);
+            let t = tables.find((t) => t.name == name);
+            if (!t) {
+                t = { name, glyphs: [] };
+                tables.push(t);
+            }
+            t.glyphs.push(bytes.map((b) => parseInt(b, 16)));
+        }
+        render();
+        preview();
+    });
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(target_arch = "x86")]
+mod board {
+    use clocklib::{ClockDisplay, Symbol};
+    use ftdi_embedded_hal as hal;
+    use is31fl3731_driver::IS31FL3731;
+    use libftd2xx::{self as ftdi};
+
+    pub struct Board {
+        clock: ClockDisplay<hal::I2c<ftdi::Ft232h>>,
+    }
+
+    impl Board {
+        pub fn open() -> Board {
+            let devices = ftdi::list_devices().expect("failed to list devices");
+            let serial = devices
+                .first()
+                .expect("no FTDI device found")
+                .serial_number
+                .clone();
+            let device = ftdi::Ft232h::with_serial_number(&serial).unwrap();
+            let hal = hal::FtHal::init_freq(device, 400_000).unwrap();
+            let i2c = hal.i2c().unwrap();
+
+            let leds = IS31FL3731::new(i2c, 0x74);
+            let mut clock = ClockDisplay::new([Some(leds), None, None]);
+            clock.setup().unwrap();
+
+            Board { clock }
+        }
+
+        pub fn show(&mut self, symbol: &Symbol) {
+            if let Err(error) = self.clock.draw_glyph(0, symbol, 0xFF) {
+                println!("Preview failed: {:?}", error);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86"))]
+mod board {
+    use clocklib::Symbol;
+
+    pub struct Board;
+
+    impl Board {
+        pub fn open() -> Board {
+            panic!("the board preview needs the FTDI driver, which is only built for x86");
+        }
+
+        pub fn show(&mut self, _symbol: &Symbol) {}
+    }
+}
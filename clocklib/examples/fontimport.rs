@@ -0,0 +1,282 @@
+//! Bitmap font importer.
+//!
+//! Rasterises the characters of a BDF or PSF font onto the segments of a
+//! cell and prints them as a glyph table for `src/glyphs.rs`. A segment is
+//! lit when the font covers more than half of it. The quality report on
+//! stderr lists the characters that need a look by hand: segments covered
+//! about half, characters that came out empty and characters that look the
+//! same as an earlier one.
+//!
+//! `cargo run --features std --example fontimport -- font.bdf [--name FONT]
+//! [--chars 0123456789] > font.txt`
+//!
+//! PSF glyphs are looked up by their index, which is the character code in
+//! the usual latin fonts.
+
+use clocklib::raster::{rasterize, Bitmap};
+use clocklib::{Symbol, SEGMENTS};
+use std::env;
+use std::fs;
+use std::process;
+
+// coverage counted as clearly dark or lit, anything between is ambiguous
+const DARK: u8 = 0x40;
+const LIT: u8 = 0xC0;
+
+/// A character as a grayscale image the size of the font bounding box.
+struct Glyph {
+    character: char,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    let mut name = "FONT".to_string();
+    let mut chars: Vec<char> = (0x21u8..0x7F).map(char::from).collect();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = args.next().unwrap_or_else(|| usage()),
+            "--chars" => chars = args.next().unwrap_or_else(|| usage()).chars().collect(),
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| usage());
+    let data = fs::read(&path).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+
+    let glyphs = if data.starts_with(b"STARTFONT") {
+        parse_bdf(&String::from_utf8_lossy(&data), &chars)
+    } else {
+        parse_psf(&data, &chars)
+    }
+    .unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+
+    let mut symbols: Vec<(char, Symbol)> = Vec::new();
+    let mut report = Vec::new();
+
+    for glyph in &glyphs {
+        let mut cell = [[0; SEGMENTS.len()]];
+        rasterize(
+            &Bitmap::new(glyph.width, glyph.height, &glyph.pixels),
+            &mut cell,
+        );
+
+        let lit: Vec<usize> = (0..SEGMENTS.len()).filter(|&i| cell[0][i] > 0x80).collect();
+        let symbol = Symbol::from_segments(&lit);
+
+        let mut issues = Vec::new();
+        let ambiguous: Vec<String> = (0..SEGMENTS.len())
+            .filter(|&i| cell[0][i] > DARK && cell[0][i] < LIT)
+            .map(|i| format!("{} ({}%)", i, cell[0][i] as u32 * 100 / 0xFF))
+            .collect();
+        if !ambiguous.is_empty() {
+            issues.push(format!("half covered segments {}", ambiguous.join(", ")));
+        }
+        if lit.is_empty() && glyph.pixels.iter().any(|&pixel| pixel > 0) {
+            issues.push("no segment lit".to_string());
+        }
+        if let Some((other, _)) = symbols.iter().find(|(_, other)| *other == symbol) {
+            issues.push(format!("same as {}", label(*other)));
+        }
+        if !issues.is_empty() {
+            report.push(format!("{}: {}", label(glyph.character), issues.join("; ")));
+        }
+
+        symbols.push((glyph.character, symbol));
+    }
+
+    println!("pub const {}: [Symbol; {}] = [", name, symbols.len());
+    for (character, symbol) in &symbols {
+        println!("    // {}", label(*character));
+        println!("    glyph!(");
+        let mut art = String::new();
+        symbol.write_art(&mut art, "        ").unwrap();
+        print!("{}", art);
+        println!("    ),");
+    }
+    println!("];");
+
+    let missing: Vec<String> = chars
+        .iter()
+        .filter(|&&c| !glyphs.iter().any(|glyph| glyph.character == c))
+        .map(|&c| label(c))
+        .collect();
+    if !missing.is_empty() {
+        eprintln!("Not in the font: {}", missing.join(" "));
+    }
+    eprintln!(
+        "{} of {} characters need a look",
+        report.len(),
+        symbols.len()
+    );
+    for line in report {
+        eprintln!("  {}", line);
+    }
+}
+
+fn parse_bdf(source: &str, chars: &[char]) -> Result<Vec<Glyph>, String> {
+    let mut font_box = None;
+    let mut glyphs = Vec::new();
+    let mut lines = source.lines();
+
+    while let Some(line) = lines.next() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("FONTBOUNDINGBOX") => font_box = Some(numbers(words)?),
+            Some("STARTCHAR") => {
+                let [width, height, x_offset, y_offset] =
+                    font_box.ok_or("STARTCHAR before FONTBOUNDINGBOX")?;
+                let (width, height) = (width as usize, height as usize);
+                let mut character = None;
+                let mut glyph_box = [width as i32, height as i32, x_offset, y_offset];
+                let mut rows: Vec<Vec<bool>> = Vec::new();
+                let mut in_bitmap = false;
+
+                for line in lines.by_ref() {
+                    let mut words = line.split_whitespace();
+                    match words.next() {
+                        Some("ENCODING") => {
+                            character = words
+                                .next()
+                                .and_then(|code| code.parse::<u32>().ok())
+                                .and_then(char::from_u32)
+                        }
+                        Some("BBX") => glyph_box = numbers(words)?,
+                        Some("BITMAP") => in_bitmap = true,
+                        Some("ENDCHAR") => break,
+                        Some(hex) if in_bitmap => rows.push(hex_bits(hex)?),
+                        _ => {}
+                    }
+                }
+
+                let character = match character {
+                    Some(character) if chars.contains(&character) => character,
+                    _ => continue,
+                };
+
+                // the glyph box is placed relative to the baseline, the
+                // bitmap covers the font box from its top left corner
+                let [glyph_width, glyph_height, glyph_x, glyph_y] = glyph_box;
+                let left = glyph_x - x_offset;
+                let top = (height as i32 + y_offset) - (glyph_y + glyph_height);
+                let mut pixels = vec![0; width * height];
+                for (y, row) in rows.iter().take(glyph_height as usize).enumerate() {
+                    for (x, &set) in row.iter().take(glyph_width as usize).enumerate() {
+                        let (px, py) = (left + x as i32, top + y as i32);
+                        if set
+                            && (0..width as i32).contains(&px)
+                            && (0..height as i32).contains(&py)
+                        {
+                            pixels[py as usize * width + px as usize] = 0xFF;
+                        }
+                    }
+                }
+
+                glyphs.push(Glyph {
+                    character,
+                    width,
+                    height,
+                    pixels,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(sorted(glyphs, chars))
+}
+
+fn parse_psf(data: &[u8], chars: &[char]) -> Result<Vec<Glyph>, String> {
+    let (offset, count, width, height, size) = if data.starts_with(&[0x36, 0x04]) {
+        let size = *data.get(3).ok_or("truncated header")? as usize;
+        let count = if data[2] & 0x01 != 0 { 512 } else { 256 };
+        (4, count, 8, size, size)
+    } else if data.starts_with(&[0x72, 0xB5, 0x4A, 0x86]) {
+        let word = |i: usize| -> Result<usize, String> {
+            let bytes = data.get(i * 4..i * 4 + 4).ok_or("truncated header")?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        };
+        (word(2)?, word(4)?, word(7)?, word(6)?, word(5)?)
+    } else {
+        return Err("neither a BDF nor a PSF font".to_string());
+    };
+
+    let stride = (width + 7) / 8;
+    let mut glyphs = Vec::new();
+
+    for &character in chars {
+        let index = character as usize;
+        if index >= count {
+            continue;
+        }
+
+        let start = offset + index * size;
+        let bytes = data.get(start..start + size).ok_or("truncated glyphs")?;
+        let mut pixels = vec![0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                if bytes[y * stride + x / 8] & (0x80 >> (x % 8)) != 0 {
+                    pixels[y * width + x] = 0xFF;
+                }
+            }
+        }
+
+        glyphs.push(Glyph {
+            character,
+            width,
+            height,
+            pixels,
+        });
+    }
+
+    Ok(glyphs)
+}
+
+fn numbers<'a>(words: impl Iterator<Item = &'a str>) -> Result<[i32; 4], String> {
+    let numbers: Vec<i32> = words
+        .map(|word| word.parse().map_err(|_| format!("not a number: {}", word)))
+        .collect::<Result<_, _>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| "expected four numbers".to_string())
+}
+
+fn hex_bits(hex: &str) -> Result<Vec<bool>, String> {
+    let mut bits = Vec::new();
+    for digit in hex.chars() {
+        let value = digit
+            .to_digit(16)
+            .ok_or_else(|| format!("bad bitmap row: {}", hex))?;
+        bits.extend((0..4).rev().map(|bit| value & (1 << bit) != 0));
+    }
+    Ok(bits)
+}
+
+// BDF fonts list characters in any order, the table follows `chars`
+fn sorted(mut glyphs: Vec<Glyph>, chars: &[char]) -> Vec<Glyph> {
+    glyphs.sort_by_key(|glyph| chars.iter().position(|&c| c == glyph.character));
+    glyphs.dedup_by_key(|glyph| glyph.character);
+    glyphs
+}
+
+fn label(character: char) -> String {
+    if character.is_ascii_graphic() {
+        character.to_string()
+    } else {
+        format!("U+{:04X}", character as u32)
+    }
+}
+
+fn usage() -> ! {
+    fail("usage: fontimport <font.bdf|font.psf> [--name NAME] [--chars CHARS]")
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
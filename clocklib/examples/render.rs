@@ -0,0 +1,22 @@
+use clocklib::render::{render_svg, segment_brightness, Emulator};
+use clocklib::ClockDisplay;
+use is31fl3731_driver::IS31FL3731;
+use std::env;
+
+// Prints an SVG of the display showing the digits given as argument,
+// e.g. `cargo run --features std --example render -- 1234 > clock.svg`
+fn main() {
+    let digits = env::args().nth(1).unwrap_or_else(|| "1234".into());
+
+    let leds1 = IS31FL3731::new(Emulator::new(), 0x76);
+    let leds2 = IS31FL3731::new(Emulator::new(), 0x74);
+    let mut clock = ClockDisplay::new([Some(leds1), Some(leds2), None]);
+    clock.setup().unwrap();
+
+    for (i, digit) in digits.chars().take(4).enumerate() {
+        let digit = digit.to_digit(10).expect("only digits can be shown");
+        clock.draw_symbol(i as u8, digit as usize, 0xFF).unwrap();
+    }
+
+    print!("{}", render_svg(&segment_brightness(&clock)));
+}
@@ -0,0 +1,278 @@
+//! Sets the clock to the time of this computer over the USB console.
+//!
+//! The clock is held at the next full second with `hold` and started half a
+//! round trip before that second begins, `start` makes the clock count its
+//! next second a full second later. With `--check` nothing is changed, the
+//! difference between the clock and this computer is printed instead.
+//!
+//! `cargo run --features std --example timesync -- /dev/ttyACM0 [--check] [--offset +01:00]`
+//!
+//! The clock keeps local time, `--offset` is its distance from UTC and
+//! defaults to the `utc-offset` setting of the clock. The port is put into
+//! raw mode with `stty`.
+
+use clocklib::calendar::DateTime;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{self, Command};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// round trips measured before setting the time, the fastest one counts
+const ROUND_TRIPS: usize = 8;
+// time left for holding the clock and setting its date before it starts
+const SETUP_MARGIN: Duration = Duration::from_millis(1000);
+// the clock must show a new second within this time when checking
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[cfg(target_os = "linux")]
+const STTY_DEVICE: &str = "-F";
+#[cfg(not(target_os = "linux"))]
+const STTY_DEVICE: &str = "-f";
+
+struct Console {
+    port: File,
+    reader: BufReader<File>,
+}
+
+impl Console {
+    fn open(path: &str) -> Result<Console, String> {
+        // raw, no echo, reads give up after 2 seconds
+        let stty = Command::new("stty")
+            .args([STTY_DEVICE, path, "raw", "-echo", "min", "0", "time", "20"])
+            .status()
+            .map_err(|error| format!("stty: {}", error))?;
+        if !stty.success() {
+            return Err(format!("stty failed on {}", path));
+        }
+
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|error| format!("{}: {}", path, error))?;
+        let reader = BufReader::new(port.try_clone().map_err(|error| error.to_string())?);
+        Ok(Console { port, reader })
+    }
+
+    /// Sends a command and returns the lines of its reply before the `ok`.
+    fn command(&mut self, command: &str) -> Result<Vec<String>, String> {
+        write!(self.port, "{}\r\n", command)
+            .and_then(|_| self.port.flush())
+            .map_err(|error| error.to_string())?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let len = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|error| error.to_string())?;
+            if len == 0 {
+                return Err(format!("no reply to `{}`", command));
+            }
+
+            match line.trim_end() {
+                "ok" => return Ok(lines),
+                "" => {}
+                line => match line.strip_prefix("error: ") {
+                    Some(error) => return Err(format!("{}: {}", command, error)),
+                    None => lines.push(line.to_string()),
+                },
+            }
+        }
+    }
+
+    /// The first line of a reply.
+    fn query(&mut self, command: &str) -> Result<String, String> {
+        self.command(command)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("empty reply to `{}`", command))
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut port = None;
+    let mut check = false;
+    let mut offset = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--offset" => {
+                let value = args.next().unwrap_or_else(|| usage());
+                offset = Some(
+                    parse_offset(value).unwrap_or_else(|| fail(&format!("bad offset `{}`", value))),
+                );
+            }
+            arg if port.is_none() && !arg.starts_with('-') => port = Some(arg),
+            _ => usage(),
+        }
+    }
+    let port = port.unwrap_or_else(|| usage());
+
+    let mut console = Console::open(port).unwrap_or_else(|error| fail(&error));
+    let offset = match offset {
+        Some(offset) => offset,
+        None => clock_offset(&mut console).unwrap_or_else(|error| fail(&error)),
+    };
+
+    let result = if check {
+        check_drift(&mut console, offset)
+    } else {
+        set_time(&mut console, offset)
+    };
+    if let Err(error) = result {
+        fail(&error);
+    }
+}
+
+/// Holds the clock at a second to come and writes its date, then starts it
+/// as that second begins.
+fn set_time(console: &mut Console, offset: i64) -> Result<(), String> {
+    let round_trip = (0..ROUND_TRIPS)
+        .map(|_| {
+            let start = Instant::now();
+            console.command("time").map(|_| start.elapsed())
+        })
+        .collect::<Result<Vec<Duration>, String>>()?
+        .into_iter()
+        .min()
+        .unwrap();
+    let ahead = round_trip / 2;
+    println!("round trip {:.1} ms", round_trip.as_secs_f64() * 1000.0);
+
+    let target = (local_now(offset) + ahead + SETUP_MARGIN).as_secs() + 1;
+    let time = DateTime::from_unix_time(target);
+    // held first, so the date cannot roll over before it is written
+    console.command(&format!(
+        "hold {:02}:{:02}:{:02}",
+        time.hours, time.minutes, time.seconds
+    ))?;
+
+    let setup = console
+        .command(&format!(
+            "date {}-{:02}-{:02}",
+            time.year, time.month, time.day
+        ))
+        .and_then(|_| {
+            let send_at = Duration::from_secs(target) - ahead;
+            let now = local_now(offset);
+            if now > send_at {
+                return Err("setting the date took too long, try again".to_string());
+            }
+            thread::sleep(send_at - now);
+            Ok(())
+        });
+    // a held clock is started even when the date failed
+    let started = console.command("start");
+    setup?;
+    started?;
+
+    println!(
+        "set to {}-{:02}-{:02} {:02}:{:02}:{:02}",
+        time.year, time.month, time.day, time.hours, time.minutes, time.seconds
+    );
+    Ok(())
+}
+
+/// Polls the time until the clock starts a new second and compares that
+/// moment with the time of this computer.
+fn check_drift(console: &mut Console, offset: i64) -> Result<(), String> {
+    let start = Instant::now();
+    let mut previous: Option<(String, Duration)> = None;
+
+    let (time, boundary) = loop {
+        if start.elapsed() > CHECK_TIMEOUT {
+            return Err("the clock did not change its time".to_string());
+        }
+
+        // the clock read its time about halfway through the round trip
+        let sent = local_now(offset);
+        let time = console.query("time")?;
+        let sampled = (sent + local_now(offset)) / 2;
+
+        match previous {
+            Some((ref shown, at)) if *shown != time => break (time, (at + sampled) / 2),
+            _ => previous = Some((time, sampled)),
+        }
+    };
+    let date = console.query("date")?;
+
+    let clock =
+        parse_date_time(&date, &time).ok_or_else(|| format!("cannot read `{} {}`", date, time))?;
+    let drift = clock.unix_time() as f64 - boundary.as_secs_f64();
+
+    println!(
+        "clock {} {}, {:.3} s {}",
+        date,
+        time,
+        drift.abs(),
+        if drift < 0.0 { "behind" } else { "ahead" }
+    );
+    Ok(())
+}
+
+/// The `utc-offset` setting of the clock in minutes.
+fn clock_offset(console: &mut Console) -> Result<i64, String> {
+    console
+        .command("settings")?
+        .iter()
+        .find_map(|line| line.strip_prefix("utc-offset "))
+        .and_then(parse_offset)
+        .ok_or_else(|| "the clock has no utc-offset setting".to_string())
+}
+
+/// Minutes from `+hh:mm` or `-hh:mm`.
+fn parse_offset(offset: &str) -> Option<i64> {
+    let (sign, rest) = match offset.as_bytes().first()? {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// Reads the replies of `date` and `time`.
+fn parse_date_time(date: &str, time: &str) -> Option<DateTime> {
+    let mut date = date.split_whitespace().next()?.split('-');
+    let mut time = time.split(':');
+    let next = |fields: &mut std::str::Split<char>| fields.next()?.parse::<u8>().ok();
+
+    let year = date.next()?.parse().ok()?;
+    let time = DateTime::new(
+        year,
+        next(&mut date)?,
+        next(&mut date)?,
+        next(&mut time)?,
+        next(&mut time)?,
+        next(&mut time)?,
+    );
+    time.is_valid().then_some(time)
+}
+
+/// The local time of the clock as time since 1970.
+fn local_now(offset: i64) -> Duration {
+    let utc = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let offset_duration = Duration::from_secs(offset.unsigned_abs() * 60);
+    if offset < 0 {
+        utc - offset_duration
+    } else {
+        utc + offset_duration
+    }
+}
+
+fn usage() -> ! {
+    fail("usage: timesync <port> [--check] [--offset +hh:mm]")
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
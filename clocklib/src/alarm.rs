@@ -0,0 +1,207 @@
+//! Alarm times and finding the next one to ring.
+//!
+//! The PCF8563 holds a single alarm, matched on minute, hour and weekday.
+//! The firmware keeps the list of alarms and programs the one returned by
+//! [`next_alarm`] whenever the list, the time or the ringing alarm changes.
+
+use crate::calendar::DateTime;
+
+pub const MAX_ALARMS: usize = 4;
+
+/// Weekday masks, bit 0 being Sunday like [`DateTime::weekday`].
+pub const EVERY_DAY: u8 = 0x7F;
+pub const WORKDAYS: u8 = 0x3E;
+pub const WEEKEND: u8 = 0x41;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Alarm {
+    pub hours: u8,
+    pub minutes: u8,
+    /// The days to ring on, see [`EVERY_DAY`].
+    pub weekdays: u8,
+    pub enabled: bool,
+}
+
+impl Alarm {
+    /// A disabled alarm at 7:00 on workdays.
+    pub const DEFAULT: Alarm = Alarm {
+        hours: 7,
+        minutes: 0,
+        weekdays: WORKDAYS,
+        enabled: false,
+    };
+
+    pub const fn rings_on(&self, weekday: u8) -> bool {
+        self.enabled && self.weekdays & (1 << weekday) != 0
+    }
+}
+
+impl Default for Alarm {
+    fn default() -> Self {
+        Alarm::DEFAULT
+    }
+}
+
+/// When an alarm rings next, as matched by the PCF8563.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlarmTime {
+    pub weekday: u8,
+    pub hours: u8,
+    pub minutes: u8,
+}
+
+impl AlarmTime {
+    /// The time `minutes` after `now`, e.g. for snoozing.
+    pub fn after(now: &DateTime, minutes: u16) -> AlarmTime {
+        let total = now.hours as u16 * 60 + now.minutes as u16 + minutes;
+        let days = total / MINUTES_PER_DAY;
+        let minute_of_day = total % MINUTES_PER_DAY;
+
+        AlarmTime {
+            weekday: ((now.weekday as u16 + days) % 7) as u8,
+            hours: (minute_of_day / 60) as u8,
+            minutes: (minute_of_day % 60) as u8,
+        }
+    }
+
+    /// Minutes from `now` until the alarm rings, from 1 up to a week. The
+    /// current minute counts as having passed.
+    pub fn minutes_from(&self, now: &DateTime) -> u16 {
+        let days = (self.weekday + 7 - now.weekday) as u16 % 7;
+        let now_minute = now.hours as u16 * 60 + now.minutes as u16;
+        let minute = days * MINUTES_PER_DAY + self.hours as u16 * 60 + self.minutes as u16;
+
+        if minute > now_minute {
+            minute - now_minute
+        } else {
+            minute + 7 * MINUTES_PER_DAY - now_minute
+        }
+    }
+}
+
+/// The enabled alarm ringing first after `now`, with its index.
+pub fn next_alarm(alarms: &[Alarm], now: &DateTime) -> Option<(usize, AlarmTime)> {
+    let mut next: Option<(usize, AlarmTime, u16)> = None;
+
+    for (index, alarm) in alarms.iter().enumerate() {
+        for weekday in 0..7 {
+            if !alarm.rings_on(weekday) {
+                continue;
+            }
+
+            let time = AlarmTime {
+                weekday,
+                hours: alarm.hours,
+                minutes: alarm.minutes,
+            };
+            let minutes = time.minutes_from(now);
+            if next.map_or(true, |(_, _, best)| minutes < best) {
+                next = Some((index, time, minutes));
+            }
+        }
+    }
+
+    next.map(|(index, time, _)| (index, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alarm(hours: u8, minutes: u8, weekdays: u8) -> Alarm {
+        Alarm {
+            hours,
+            minutes,
+            weekdays,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn next_alarm_rings_later_today() {
+        // a Wednesday
+        let now = DateTime::new(2023, 10, 18, 6, 30, 0);
+        let next = next_alarm(&[alarm(7, 0, EVERY_DAY)], &now);
+        let time = AlarmTime {
+            weekday: 3,
+            hours: 7,
+            minutes: 0,
+        };
+        assert_eq!(next, Some((0, time)));
+        assert_eq!(time.minutes_from(&now), 30);
+    }
+
+    #[test]
+    fn next_alarm_skips_a_time_already_past_today() {
+        let now = DateTime::new(2023, 10, 18, 7, 30, 0);
+        let next = next_alarm(&[alarm(7, 0, EVERY_DAY)], &now);
+        assert_eq!(next.map(|(_, time)| time.weekday), Some(4));
+    }
+
+    #[test]
+    fn the_current_minute_counts_as_past() {
+        let now = DateTime::new(2023, 10, 18, 7, 0, 30);
+        let time = AlarmTime {
+            weekday: 3,
+            hours: 7,
+            minutes: 0,
+        };
+        assert_eq!(time.minutes_from(&now), 7 * 24 * 60);
+    }
+
+    #[test]
+    fn next_alarm_wraps_around_the_week() {
+        // a Saturday, workday alarms ring on Monday
+        let now = DateTime::new(2024, 1, 6, 8, 0, 0);
+        let next = next_alarm(&[alarm(7, 0, WORKDAYS)], &now);
+        let (_, time) = next.unwrap();
+        assert_eq!(time.weekday, 1);
+        assert_eq!(time.minutes_from(&now), 47 * 60);
+    }
+
+    #[test]
+    fn next_alarm_picks_the_earliest() {
+        let now = DateTime::new(2023, 10, 18, 12, 0, 0);
+        let alarms = [
+            alarm(7, 0, EVERY_DAY),
+            alarm(13, 15, WEEKEND),
+            alarm(22, 0, WORKDAYS),
+        ];
+        assert_eq!(next_alarm(&alarms, &now).map(|(index, _)| index), Some(2));
+    }
+
+    #[test]
+    fn next_alarm_ignores_disabled_alarms() {
+        let now = DateTime::new(2023, 10, 18, 12, 0, 0);
+        let mut early = alarm(12, 1, EVERY_DAY);
+        early.enabled = false;
+        let alarms = [early, alarm(18, 0, EVERY_DAY)];
+        assert_eq!(next_alarm(&alarms, &now).map(|(index, _)| index), Some(1));
+        assert_eq!(next_alarm(&[early], &now), None);
+    }
+
+    #[test]
+    fn next_alarm_without_alarms_or_days() {
+        let now = DateTime::new(2023, 10, 18, 12, 0, 0);
+        assert_eq!(next_alarm(&[], &now), None);
+        assert_eq!(next_alarm(&[alarm(7, 0, 0)], &now), None);
+    }
+
+    #[test]
+    fn snooze_wraps_into_the_next_day() {
+        // a Saturday night
+        let now = DateTime::new(2024, 1, 6, 23, 55, 0);
+        let time = AlarmTime::after(&now, 9);
+        assert_eq!(
+            time,
+            AlarmTime {
+                weekday: 0,
+                hours: 0,
+                minutes: 4,
+            }
+        );
+        assert_eq!(time.minutes_from(&now), 9);
+    }
+}
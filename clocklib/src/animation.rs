@@ -0,0 +1,551 @@
+//! Keyframe animations stored as data.
+//!
+//! An animation is a sequence of frames, each shown for a number of
+//! milliseconds. A frame only lists the cells that change, the others keep
+//! what they showed before. The binary format, all numbers little endian:
+//!
+//! ```text
+//! header  "MA" version:u8 flags:u8          flags bit 0: loop
+//! frame   duration_ms:u16 records:u8 record*
+//! record  kind << 4 | cell, then by kind:
+//!         0 glyph    table:u8 index:u8 level:u8   a glyph of glyphs::TABLES
+//!         1 mask     mask:[u8; 6] level:u8        a symbol mask
+//!         2 levels   level:[u8; 44]               every segment on its own
+//! ```
+//!
+//! [`Player`] steps through an animation without allocating. With the `std`
+//! feature [`encode`] builds the binary form from a text description, see
+//! the `animation` example.
+
+use crate::glyphs::{Symbol, TABLES};
+use crate::{CELLS, SEGMENTS};
+
+pub const MAGIC: [u8; 2] = *b"MA";
+pub const VERSION: u8 = 1;
+
+/// Header flag: start over after the last frame.
+pub const FLAG_LOOP: u8 = 0x01;
+
+const HEADER_LEN: usize = 4;
+const FRAME_HEADER_LEN: usize = 3;
+
+const KIND_GLYPH: u8 = 0;
+const KIND_MASK: u8 = 1;
+const KIND_LEVELS: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    /// The data ends inside a frame.
+    Truncated,
+    /// A record for a cell the display does not have.
+    InvalidCell(u8),
+    InvalidKind(u8),
+    /// A glyph reference outside [`TABLES`].
+    InvalidGlyph {
+        table: u8,
+        index: u8,
+    },
+    /// The animation has no frames.
+    Empty,
+}
+
+/// What a record draws on its cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Content<'a> {
+    Symbol { symbol: Symbol, level: u8 },
+    Levels(&'a [u8]),
+}
+
+/// A checked animation, borrowing its binary form.
+#[derive(Clone, Copy, Debug)]
+pub struct Animation<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Animation<'a> {
+    /// Checks every frame of `data`, so playing it cannot fail.
+    pub fn new(data: &'a [u8]) -> Result<Animation<'a>, AnimationError> {
+        if data.len() < HEADER_LEN || data[..2] != MAGIC {
+            return Err(AnimationError::BadMagic);
+        }
+        if data[2] != VERSION {
+            return Err(AnimationError::UnsupportedVersion(data[2]));
+        }
+
+        let animation = Animation { data };
+        let mut offset = HEADER_LEN;
+        if offset == data.len() {
+            return Err(AnimationError::Empty);
+        }
+        while offset < data.len() {
+            let (_, records, next) = animation.frame_header(offset)?;
+            offset = next;
+            for _ in 0..records {
+                let (_, _, next) = animation.record(offset)?;
+                offset = next;
+            }
+        }
+
+        Ok(animation)
+    }
+
+    pub fn looping(&self) -> bool {
+        self.data[3] & FLAG_LOOP != 0
+    }
+
+    fn frame_header(&self, offset: usize) -> Result<(u16, u8, usize), AnimationError> {
+        let header = self
+            .data
+            .get(offset..offset + FRAME_HEADER_LEN)
+            .ok_or(AnimationError::Truncated)?;
+        let duration = u16::from_le_bytes([header[0], header[1]]);
+        Ok((duration, header[2], offset + FRAME_HEADER_LEN))
+    }
+
+    fn record(&self, offset: usize) -> Result<(usize, Content<'a>, usize), AnimationError> {
+        let data = self.data;
+        let &tag = data.get(offset).ok_or(AnimationError::Truncated)?;
+        let cell = tag & 0x0F;
+        if cell as usize >= CELLS {
+            return Err(AnimationError::InvalidCell(cell));
+        }
+
+        let body = offset + 1;
+        let bytes = |len: usize| data.get(body..body + len).ok_or(AnimationError::Truncated);
+        let (content, len) = match tag >> 4 {
+            KIND_GLYPH => {
+                let record = bytes(3)?;
+                let (table, index) = (record[0], record[1]);
+                let symbol = TABLES
+                    .get(table as usize)
+                    .and_then(|(_, glyphs)| glyphs.get(index as usize))
+                    .ok_or(AnimationError::InvalidGlyph { table, index })?;
+                (
+                    Content::Symbol {
+                        symbol: *symbol,
+                        level: record[2],
+                    },
+                    3,
+                )
+            }
+            KIND_MASK => {
+                let record = bytes(7)?;
+                let mut mask = [0; 6];
+                mask.copy_from_slice(&record[..6]);
+                (
+                    Content::Symbol {
+                        symbol: Symbol::from_mask(mask),
+                        level: record[6],
+                    },
+                    7,
+                )
+            }
+            KIND_LEVELS => (Content::Levels(bytes(SEGMENTS.len())?), SEGMENTS.len()),
+            kind => return Err(AnimationError::InvalidKind(kind)),
+        };
+
+        Ok((cell as usize, content, body + len))
+    }
+}
+
+/// Plays an animation into a level per segment of every cell, ready for
+/// [`ClockDisplay::draw_levels`](crate::ClockDisplay::draw_levels).
+pub struct Player<'a> {
+    animation: Animation<'a>,
+    offset: usize,
+    levels: [[u8; SEGMENTS.len()]; CELLS],
+}
+
+impl<'a> Player<'a> {
+    /// Starts with all cells dark.
+    pub fn new(animation: Animation<'a>) -> Player<'a> {
+        Player {
+            animation,
+            offset: HEADER_LEN,
+            levels: [[0; SEGMENTS.len()]; CELLS],
+        }
+    }
+
+    /// Applies the next frame and returns how long it should be shown, or
+    /// `None` after the last frame of an animation that does not loop.
+    pub fn step(&mut self) -> Option<u16> {
+        if self.offset == self.animation.data.len() {
+            if !self.animation.looping() {
+                return None;
+            }
+            self.offset = HEADER_LEN;
+        }
+
+        // the animation was checked when it was created
+        let (duration, records, mut offset) = self.animation.frame_header(self.offset).unwrap();
+        for _ in 0..records {
+            let (cell, content, next) = self.animation.record(offset).unwrap();
+            offset = next;
+
+            let levels = &mut self.levels[cell];
+            match content {
+                Content::Symbol { symbol, level } => {
+                    for (i, segment) in levels.iter_mut().enumerate() {
+                        *segment = if symbol.is_lit(i) { level } else { 0 };
+                    }
+                }
+                Content::Levels(values) => levels.copy_from_slice(values),
+            }
+        }
+        self.offset = offset;
+
+        Some(duration)
+    }
+
+    /// Whether the last frame was just applied.
+    pub fn at_end(&self) -> bool {
+        self.offset == self.animation.data.len()
+    }
+
+    /// The levels after the last step.
+    pub fn levels(&self) -> &[[u8; SEGMENTS.len()]; CELLS] {
+        &self.levels
+    }
+}
+
+/// Encodes the text form of an animation:
+///
+/// ```text
+/// # comments start with a hash
+/// loop                            optional, play forever
+/// frame 120                       a frame shown for 120 ms
+///   0 glyph DIGITS 8 255          cell 0 shows DIGITS[8] at full level
+///   1 icon bell 128               an icon by name, see glyphs::Icon
+///   2 mask 0f0000000000 64        a symbol mask as 12 hex digits
+///   3 levels 0 0 255 ...          44 levels
+///   * off                         every cell dark
+/// ```
+///
+/// The level of `glyph`, `icon` and `mask` defaults to 255.
+#[cfg(feature = "std")]
+pub fn encode(source: &str) -> Result<Vec<u8>, String> {
+    use crate::glyphs::Icon;
+
+    let mut data = Vec::from(MAGIC);
+    data.extend([VERSION, 0]);
+    // position of the record count of the current frame
+    let mut frame: Option<usize> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let words: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+
+        match words.as_slice() {
+            [] => {}
+            ["loop"] => data[3] |= FLAG_LOOP,
+            ["frame", duration] => {
+                let duration: u16 = duration.parse().map_err(|_| error("bad duration"))?;
+                data.extend(duration.to_le_bytes());
+                frame = Some(data.len());
+                data.push(0);
+            }
+            [cell, kind, args @ ..] => {
+                let count = frame.ok_or_else(|| error("cell before the first frame"))?;
+                let cells = match *cell {
+                    "*" => 0..CELLS as u8,
+                    cell => {
+                        let cell: u8 = cell.parse().map_err(|_| error("bad cell"))?;
+                        if cell as usize >= CELLS {
+                            return Err(error("no such cell"));
+                        }
+                        cell..cell + 1
+                    }
+                };
+                let level = |i: usize| -> Result<u8, String> {
+                    args.get(i)
+                        .map_or(Ok(0xFF), |level| level.parse())
+                        .map_err(|_| error("bad level"))
+                };
+
+                let (kind, body) = match (*kind, args) {
+                    ("glyph", [table, index, ..]) => {
+                        let table = TABLES
+                            .iter()
+                            .position(|(name, _)| name == table)
+                            .ok_or_else(|| error("no such table"))?;
+                        let index: u8 = index.parse().map_err(|_| error("bad glyph index"))?;
+                        if index as usize >= TABLES[table].1.len() {
+                            return Err(error("no such glyph"));
+                        }
+                        (KIND_GLYPH, vec![table as u8, index, level(2)?])
+                    }
+                    ("icon", [name, ..]) => {
+                        let icon = Icon::from_name(name).ok_or_else(|| error("no such icon"))?;
+                        let table = TABLES
+                            .iter()
+                            .position(|(name, _)| *name == "ICONS")
+                            .unwrap();
+                        (KIND_GLYPH, vec![table as u8, icon as u8, level(1)?])
+                    }
+                    ("mask", [hex, ..]) => {
+                        if hex.len() != 12 || !hex.is_ascii() {
+                            return Err(error("a mask has 12 hex digits"));
+                        }
+                        let mut body = (0..6)
+                            .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16))
+                            .collect::<Result<Vec<u8>, _>>()
+                            .map_err(|_| error("bad mask"))?;
+                        body.push(level(1)?);
+                        (KIND_MASK, body)
+                    }
+                    ("levels", levels) if levels.len() == SEGMENTS.len() => {
+                        let levels = (0..levels.len())
+                            .map(level)
+                            .collect::<Result<Vec<u8>, String>>()?;
+                        (KIND_LEVELS, levels)
+                    }
+                    ("off", []) => (KIND_MASK, vec![0; 7]),
+                    _ => return Err(error("unknown record")),
+                };
+
+                for cell in cells {
+                    if data[count] == u8::MAX {
+                        return Err(error("too many records in the frame"));
+                    }
+                    data[count] += 1;
+                    data.push(kind << 4 | cell);
+                    data.extend(&body);
+                }
+            }
+            _ => return Err(error("expected `loop`, `frame <ms>` or a cell")),
+        }
+    }
+
+    Animation::new(&data).map_err(|error| format!("{:?}", error))?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyphs::DIGITS;
+
+    const LIT: [u8; 6] = [0xFF; 6];
+    const DARK: [u8; 6] = [0; 6];
+
+    fn animation(flags: u8, frames: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![MAGIC[0], MAGIC[1], VERSION, flags];
+        for frame in frames {
+            data.extend_from_slice(frame);
+        }
+        data
+    }
+
+    fn frame(duration: u16, records: &[&[u8]]) -> Vec<u8> {
+        let mut frame = duration.to_le_bytes().to_vec();
+        frame.push(records.len() as u8);
+        for record in records {
+            frame.extend_from_slice(record);
+        }
+        frame
+    }
+
+    fn mask(cell: u8, mask: [u8; 6], level: u8) -> Vec<u8> {
+        let mut record = vec![KIND_MASK << 4 | cell];
+        record.extend_from_slice(&mask);
+        record.push(level);
+        record
+    }
+
+    fn cell_levels(level: u8) -> [u8; SEGMENTS.len()] {
+        [level; SEGMENTS.len()]
+    }
+
+    #[test]
+    fn truncated_headers() {
+        assert_eq!(Animation::new(b"").unwrap_err(), AnimationError::BadMagic);
+        assert_eq!(
+            Animation::new(b"MA\x01").unwrap_err(),
+            AnimationError::BadMagic
+        );
+        assert_eq!(
+            Animation::new(&animation(0, &[])).unwrap_err(),
+            AnimationError::Empty
+        );
+        // a frame header cut short
+        assert_eq!(
+            Animation::new(&animation(0, &[&[100, 0]])).unwrap_err(),
+            AnimationError::Truncated
+        );
+    }
+
+    #[test]
+    fn bad_magic_and_version() {
+        let mut data = animation(0, &[&frame(100, &[])]);
+        assert!(Animation::new(&data).is_ok());
+
+        data[0] = b'X';
+        assert_eq!(Animation::new(&data).unwrap_err(), AnimationError::BadMagic);
+
+        let mut data = animation(0, &[&frame(100, &[])]);
+        data[2] = VERSION + 1;
+        assert_eq!(
+            Animation::new(&data).unwrap_err(),
+            AnimationError::UnsupportedVersion(VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn record_counts_overrunning_the_data() {
+        let mut first = frame(100, &[&mask(0, LIT, 255)]);
+        // claims a second record that is not there
+        first[2] = 2;
+        assert_eq!(
+            Animation::new(&animation(0, &[&first])).unwrap_err(),
+            AnimationError::Truncated
+        );
+
+        let mut cut = frame(100, &[&mask(0, LIT, 255)]);
+        cut.pop();
+        assert_eq!(
+            Animation::new(&animation(0, &[&cut])).unwrap_err(),
+            AnimationError::Truncated
+        );
+
+        let levels = frame(100, &[&[KIND_LEVELS << 4, 1, 2, 3]]);
+        assert_eq!(
+            Animation::new(&animation(0, &[&levels])).unwrap_err(),
+            AnimationError::Truncated
+        );
+    }
+
+    #[test]
+    fn invalid_records() {
+        let check =
+            |record: &[u8]| Animation::new(&animation(0, &[&frame(100, &[record])])).map(|_| ());
+
+        assert_eq!(
+            check(&mask(CELLS as u8, LIT, 255)).unwrap_err(),
+            AnimationError::InvalidCell(CELLS as u8)
+        );
+        assert_eq!(
+            check(&[3 << 4, 0, 0, 0]).unwrap_err(),
+            AnimationError::InvalidKind(3)
+        );
+        assert_eq!(
+            check(&[KIND_GLYPH << 4, TABLES.len() as u8, 0, 255]).unwrap_err(),
+            AnimationError::InvalidGlyph {
+                table: TABLES.len() as u8,
+                index: 0
+            }
+        );
+        assert_eq!(
+            check(&[KIND_GLYPH << 4, 0, DIGITS.len() as u8, 255]).unwrap_err(),
+            AnimationError::InvalidGlyph {
+                table: 0,
+                index: DIGITS.len() as u8
+            }
+        );
+    }
+
+    #[test]
+    fn playing_once() {
+        let mut levels = vec![KIND_LEVELS << 4 | 2];
+        levels.extend((0..SEGMENTS.len()).map(|i| i as u8));
+        let data = animation(
+            0,
+            &[
+                &frame(100, &[&mask(0, LIT, 200), &mask(1, LIT, 50)]),
+                &frame(250, &[&mask(1, DARK, 255), &levels]),
+            ],
+        );
+        let animation = Animation::new(&data).unwrap();
+        assert!(!animation.looping());
+
+        let mut player = Player::new(animation);
+        assert_eq!(player.levels(), &[cell_levels(0); CELLS]);
+
+        assert_eq!(player.step(), Some(100));
+        assert!(!player.at_end());
+        assert_eq!(player.levels()[0], cell_levels(200));
+        assert_eq!(player.levels()[1], cell_levels(50));
+        assert_eq!(player.levels()[2], cell_levels(0));
+
+        assert_eq!(player.step(), Some(250));
+        assert!(player.at_end());
+        // cells without a record keep what they showed
+        assert_eq!(player.levels()[0], cell_levels(200));
+        assert_eq!(player.levels()[1], cell_levels(0));
+        assert_eq!(player.levels()[2][..3], [0, 1, 2]);
+
+        assert_eq!(player.step(), None);
+        assert_eq!(player.step(), None);
+    }
+
+    #[test]
+    fn looping_starts_over() {
+        let data = animation(
+            FLAG_LOOP,
+            &[
+                &frame(10, &[&mask(3, LIT, 255)]),
+                &frame(20, &[&mask(3, DARK, 255)]),
+            ],
+        );
+        let mut player = Player::new(Animation::new(&data).unwrap());
+
+        for _ in 0..3 {
+            assert_eq!(player.step(), Some(10));
+            assert_eq!(player.levels()[3], cell_levels(255));
+            assert_eq!(player.step(), Some(20));
+            assert_eq!(player.levels()[3], cell_levels(0));
+            assert!(player.at_end());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn encoding() {
+        let data = encode(
+            "loop\n\
+             frame 120 # first\n\
+             \x20 0 glyph DIGITS 8\n\
+             \x20 * mask ffffffffffff 7\n\
+             frame 30\n\
+             \x20 * off\n",
+        )
+        .unwrap();
+        let animation = Animation::new(&data).unwrap();
+        assert!(animation.looping());
+
+        let mut player = Player::new(animation);
+        assert_eq!(player.step(), Some(120));
+        assert_eq!(player.levels(), &[cell_levels(7); CELLS]);
+        assert_eq!(player.step(), Some(30));
+        assert_eq!(player.levels(), &[cell_levels(0); CELLS]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn encoding_errors() {
+        let error = |source: &str| encode(source).unwrap_err();
+
+        assert_eq!(error("0 off"), "line 1: cell before the first frame");
+        assert_eq!(error("frame 10\n4 off"), "line 2: no such cell");
+        assert_eq!(
+            error("frame 10\n0 mask 0f00"),
+            "line 2: a mask has 12 hex digits"
+        );
+        assert_eq!(error("frame 10\n0 mask 0f000000000g"), "line 2: bad mask");
+        // 12 bytes, but not 12 digits
+        assert_eq!(
+            error("frame 10\n0 mask 0\u{e9}000000000"),
+            "line 2: a mask has 12 hex digits"
+        );
+        assert_eq!(error("frame 10\n0 glyph NOPE 0"), "line 2: no such table");
+        assert_eq!(error("frame 10\n0 icon nope"), "line 2: no such icon");
+        assert_eq!(error("frame 10\n0 levels 1 2"), "line 2: unknown record");
+        assert_eq!(error("frame 10\n0 off 1"), "line 2: unknown record");
+        assert_eq!(
+            error("frames"),
+            "line 1: expected `loop`, `frame <ms>` or a cell"
+        );
+        assert_eq!(error(""), "Empty");
+    }
+}
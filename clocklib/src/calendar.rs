@@ -0,0 +1,332 @@
+//! Gregorian calendar arithmetic for the software clock.
+//!
+//! The firmware counts seconds between RTC syncs, [`DateTime::tick`] keeps
+//! the date valid across month and year ends. Weekdays count from Sunday as
+//! 0, like the examples in the PCF8563 datasheet.
+//!
+//! The PCF8563 only stores two year digits and a century flag, which it
+//! toggles when the year wraps from 99 to 00. A cleared flag is taken as
+//! the years from [`BASE_YEAR`], a set one as the century after.
+
+/// First year of the century with the PCF8563 century flag cleared.
+pub const BASE_YEAR: u16 = 2000;
+
+/// Years from [`BASE_YEAR`] the PCF8563 tells apart, its century flag
+/// covers two centuries.
+pub const RTC_YEARS: u16 = 200;
+
+pub const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to the length of the month.
+    pub day: u8,
+    /// 0 (Sunday) to 6, derived from the date.
+    pub weekday: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+pub const fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The weekday of a date, 0 being Sunday. Months out of range count as
+/// the nearest valid one, so dates read from a confused RTC don't panic.
+pub const fn weekday(year: u16, month: u8, day: u8) -> u8 {
+    // Sakamoto's method, January and February count as months of the
+    // previous year
+    const OFFSETS: [u32; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let month = if month < 1 {
+        1
+    } else if month > 12 {
+        12
+    } else {
+        month
+    };
+    let year = if month < 3 {
+        year.saturating_sub(1)
+    } else {
+        year
+    } as u32;
+    ((year + year / 4 - year / 100 + year / 400 + OFFSETS[month as usize - 1] + day as u32) % 7)
+        as u8
+}
+
+/// The full year from the PCF8563 year register and century flag.
+pub const fn from_rtc_year(year: u8, century: bool) -> u16 {
+    BASE_YEAR + if century { 100 } else { 0 } + year as u16 % 100
+}
+
+/// The PCF8563 year register and century flag for a full year.
+pub const fn to_rtc_year(year: u16) -> (u8, bool) {
+    let years = year.saturating_sub(BASE_YEAR);
+    ((years % 100) as u8, (years / 100) % 2 == 1)
+}
+
+impl DateTime {
+    pub const DEFAULT: DateTime = DateTime::new(BASE_YEAR, 1, 1, 0, 0, 0);
+
+    /// A date and time with the weekday filled in.
+    pub const fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+    ) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            weekday: weekday(year, month, day),
+            hours,
+            minutes,
+            seconds,
+        }
+    }
+
+    /// Whether every field is in range and the weekday matches the date.
+    pub const fn is_valid(&self) -> bool {
+        self.year >= BASE_YEAR
+            && self.month >= 1
+            && self.month <= 12
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.weekday == weekday(self.year, self.month, self.day)
+            && self.hours < 24
+            && self.minutes < 60
+            && self.seconds < 60
+    }
+
+    /// The date and time `seconds` after 1970-01-01 00:00:00.
+    pub const fn from_unix_time(seconds: u64) -> DateTime {
+        // days counted from 0000-03-01, so leap days end the years
+        let days = seconds / 86400 + 719468;
+        let era = days / 146097;
+        let day_of_era = days % 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+
+        let time = seconds % 86400;
+        DateTime::new(
+            year as u16,
+            month as u8,
+            day as u8,
+            (time / 3600) as u8,
+            (time / 60 % 60) as u8,
+            (time % 60) as u8,
+        )
+    }
+
+    /// Seconds since 1970-01-01 00:00:00.
+    pub const fn unix_time(&self) -> u64 {
+        let month = self.month as u64;
+        let year = self.year as u64 - if month <= 2 { 1 } else { 0 };
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_from_march + 2) / 5 + self.day as u64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        days * 86400 + self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64
+    }
+
+    /// Advances by one second.
+    pub fn tick(&mut self) {
+        self.seconds += 1;
+        if self.seconds >= 60 {
+            self.seconds = 0;
+            self.minutes += 1;
+        }
+        if self.minutes >= 60 {
+            self.minutes = 0;
+            self.hours += 1;
+        }
+        if self.hours >= 24 {
+            self.hours = 0;
+            self.next_day();
+        }
+    }
+
+    /// Moves to the next day, keeping the time.
+    pub fn next_day(&mut self) {
+        self.day += 1;
+        if self.day > days_in_month(self.year, self.month) {
+            self.day = 1;
+            self.month += 1;
+        }
+        if self.month > 12 {
+            self.month = 1;
+            self.year += 1;
+        }
+        self.weekday = weekday(self.year, self.month, self.day);
+    }
+
+    /// Clamps the day to the length of the month, e.g. after changing the
+    /// month of the 31st, and derives the weekday again.
+    pub fn normalize(&mut self) {
+        self.month = self.month.clamp(1, 12);
+        self.day = self.day.clamp(1, days_in_month(self.year, self.month));
+        self.weekday = weekday(self.year, self.month, self.day);
+    }
+}
+
+impl Default for DateTime {
+    fn default() -> Self {
+        DateTime::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_rolls_over_minutes_hours_and_days() {
+        let mut time = DateTime::new(2023, 6, 30, 23, 59, 59);
+        time.tick();
+        assert_eq!(time, DateTime::new(2023, 7, 1, 0, 0, 0));
+    }
+
+    #[test]
+    fn tick_rolls_over_the_year() {
+        let mut time = DateTime::new(2023, 12, 31, 23, 59, 59);
+        time.tick();
+        assert_eq!(time, DateTime::new(2024, 1, 1, 0, 0, 0));
+        assert_eq!(time.weekday, 1);
+    }
+
+    #[test]
+    fn next_day_keeps_the_time() {
+        let mut time = DateTime::new(2023, 4, 30, 12, 34, 56);
+        time.next_day();
+        assert_eq!(time, DateTime::new(2023, 5, 1, 12, 34, 56));
+    }
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2000));
+        assert!(is_leap_year(2024));
+        assert!(!is_leap_year(2023));
+        assert!(!is_leap_year(2100));
+        assert!(is_leap_year(2400));
+
+        let mut time = DateTime::new(2024, 2, 28, 0, 0, 0);
+        time.next_day();
+        assert_eq!((time.month, time.day), (2, 29));
+        let mut time = DateTime::new(2100, 2, 28, 0, 0, 0);
+        time.next_day();
+        assert_eq!((time.month, time.day), (3, 1));
+        let mut time = DateTime::new(2000, 2, 28, 0, 0, 0);
+        time.next_day();
+        assert_eq!((time.month, time.day), (2, 29));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(weekday(2000, 1, 1), 6);
+        assert_eq!(weekday(2000, 2, 29), 2);
+        assert_eq!(weekday(2023, 6, 28), 3);
+        assert_eq!(weekday(2100, 3, 1), 1);
+        assert_eq!(weekday(2199, 12, 31), 2);
+    }
+
+    #[test]
+    fn weekday_of_garbage_does_not_panic() {
+        for month in [0, 13, 31, 0xFF] {
+            for day in [0, 32, 0xFF] {
+                assert!(weekday(2000, month, day) < 7);
+            }
+        }
+        assert!(weekday(0, 1, 1) < 7);
+        assert!(weekday(u16::MAX, 12, 31) < 7);
+
+        let mut time = DateTime::new(2023, 0, 45, 0, 0, 0);
+        time.normalize();
+        assert!(time.is_valid());
+        assert_eq!((time.month, time.day), (1, 31));
+    }
+
+    #[test]
+    fn normalize_clamps_the_day() {
+        let mut time = DateTime::new(2023, 2, 31, 0, 0, 0);
+        time.normalize();
+        assert_eq!(time, DateTime::new(2023, 2, 28, 0, 0, 0));
+    }
+
+    #[test]
+    fn rtc_years() {
+        assert_eq!(from_rtc_year(0, false), 2000);
+        assert_eq!(from_rtc_year(99, false), 2099);
+        assert_eq!(from_rtc_year(0, true), 2100);
+        assert_eq!(from_rtc_year(99, true), 2199);
+
+        assert_eq!(to_rtc_year(2000), (0, false));
+        assert_eq!(to_rtc_year(2099), (99, false));
+        assert_eq!(to_rtc_year(2100), (0, true));
+        assert_eq!(to_rtc_year(2199), (99, true));
+        // years before the base count as the base
+        assert_eq!(to_rtc_year(1999), (0, false));
+
+        for year in BASE_YEAR..BASE_YEAR + RTC_YEARS {
+            let (rtc_year, century) = to_rtc_year(year);
+            assert_eq!(from_rtc_year(rtc_year, century), year);
+        }
+    }
+
+    #[test]
+    fn unix_time() {
+        assert_eq!(DateTime::new(1970, 1, 1, 0, 0, 0).unix_time(), 0);
+        assert_eq!(
+            DateTime::from_unix_time(946_684_800),
+            DateTime::new(2000, 1, 1, 0, 0, 0)
+        );
+        assert_eq!(DateTime::new(2038, 1, 19, 3, 14, 8).unix_time(), 1 << 31);
+    }
+
+    #[test]
+    fn unix_time_round_trip() {
+        let start = DateTime::new(BASE_YEAR, 1, 1, 0, 0, 0).unix_time();
+        let end = DateTime::new(BASE_YEAR + RTC_YEARS, 1, 1, 0, 0, 0).unix_time();
+        let mut expected = DateTime::new(BASE_YEAR, 1, 1, 12, 34, 56);
+        for seconds in (start..end).step_by(86400) {
+            let time = DateTime::from_unix_time(seconds + 45296);
+            assert_eq!(time, expected);
+            assert_eq!(time.unix_time(), seconds + 45296);
+            expected.next_day();
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Per segment brightness compensation.
+//!
+//! Segments are lit by one to three LEDs and differ in size, so at the same
+//! colour some look brighter than others. A [`Calibration`] holds a gain for
+//! every segment which `draw_segment` scales the colour with.
+
+use crate::SEGMENTS;
+
+/// Gain of a segment that is not dimmed.
+pub const UNITY: u8 = 0xFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    pub gains: [u8; SEGMENTS.len()],
+}
+
+impl Calibration {
+    /// Evens out the light per area from the number of LEDs of each segment.
+    /// The segment with the fewest LEDs for its size keeps full brightness.
+    pub const LED_DENSITY: Calibration = Calibration::from_led_density();
+
+    /// Leaves every segment at full brightness.
+    pub const UNIFORM: Calibration = Calibration::new([UNITY; SEGMENTS.len()]);
+
+    pub const fn new(gains: [u8; SEGMENTS.len()]) -> Calibration {
+        Calibration { gains }
+    }
+
+    const fn from_led_density() -> Calibration {
+        // LEDs per area, scaled up to keep precision
+        const fn density(segment: usize) -> u32 {
+            SEGMENTS[segment].leds.len() as u32 * 1_000_000 / SEGMENTS[segment].area as u32
+        }
+
+        let mut lowest = u32::MAX;
+        let mut i = 0;
+        while i < SEGMENTS.len() {
+            if density(i) < lowest {
+                lowest = density(i);
+            }
+            i += 1;
+        }
+
+        let mut gains = [UNITY; SEGMENTS.len()];
+        let mut i = 0;
+        while i < SEGMENTS.len() {
+            gains[i] = (UNITY as u32 * lowest / density(i)) as u8;
+            i += 1;
+        }
+
+        Calibration { gains }
+    }
+
+    /// Scales `color` by the gain of `segment`. A lit segment stays lit.
+    pub fn apply(&self, segment: usize, color: u8) -> u8 {
+        let scaled = (color as u16 * self.gains[segment] as u16 / UNITY as u16) as u8;
+        if color > 0 && scaled == 0 {
+            1
+        } else {
+            scaled
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration::LED_DENSITY
+    }
+}
@@ -0,0 +1,542 @@
+//! The command set of the USB serial console.
+//!
+//! The firmware reads a line at a time, [`parse`] turns it into a
+//! [`Command`] and the `write_*` functions format the replies. Every reply
+//! ends with a line `ok` or `error: <reason>`, lines end with CR LF. See
+//! [`HELP`] for the commands.
+
+use core::fmt::{self, Write};
+
+use crate::alarm::Alarm;
+use crate::calendar::{self, DateTime, BASE_YEAR, RTC_YEARS, WEEKDAY_NAMES};
+use crate::glyphs::Symbol;
+use crate::orientation::Orientation;
+use crate::settings::{HourFormat, Settings};
+use crate::{seven_segment, CELLS};
+
+/// The names of the orientations in commands and replies.
+const ORIENTATION_NAMES: [(Orientation, &str); 4] = [
+    (Orientation::Normal, "normal"),
+    (Orientation::Rotated180, "rotated-180"),
+    (Orientation::MirroredHorizontally, "mirrored-horizontally"),
+    (Orientation::MirroredVertically, "mirrored-vertically"),
+];
+
+const MIN_UTC_OFFSET: i16 = -12 * 60;
+const MAX_UTC_OFFSET: i16 = 14 * 60;
+
+pub const HELP: &str = "\
+time [hh:mm[:ss]]     show or set the time\r
+date [yyyy-mm-dd]     show or set the date\r
+hold <hh:mm[:ss]>     stop the clock at a time\r
+start                 run the clock again, its next second begins in 1 s\r
+brightness [level]    show or set the brightness level\r
+orientation [name]    show or set how the display is mounted: normal,\r
+                      rotated-180, mirrored-horizontally or mirrored-vertically\r
+transitions [on|off]  show or set whether digits animate as they change\r
+utc-offset [+hh:mm]   show or set how far the clock is ahead of UTC\r
+show <text>           show up to 4 characters until a button is pressed\r
+selftest              light every segment in turn\r
+settings              list the settings\r
+alarms                list the alarms\r
+bootloader            restart into the USB bootloader\r
+help                  list the commands\r
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    /// Shows the time, or sets hours, minutes and seconds.
+    Time(Option<(u8, u8, u8)>),
+    /// Shows the date, or sets year, month and day.
+    Date(Option<(u16, u8, u8)>),
+    /// Stops the clock at hours, minutes and seconds until [`Command::Start`],
+    /// for setting it to the fraction of a second.
+    Hold((u8, u8, u8)),
+    Start,
+    Brightness(Option<u8>),
+    Orientation(Option<Orientation>),
+    Transitions(Option<bool>),
+    /// Shows or sets the minutes the local time is ahead of UTC.
+    UtcOffset(Option<i16>),
+    Show([Symbol; CELLS]),
+    SelfTest,
+    Settings,
+    Alarms,
+    Bootloader,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError<'a> {
+    Empty,
+    UnknownCommand(&'a str),
+    BadArgument(&'a str),
+    MissingArgument,
+    TooManyArguments,
+    /// More characters than cells.
+    TextTooLong,
+    /// A character without a 7-segment shape.
+    Unprintable(char),
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty line"),
+            ParseError::UnknownCommand(command) => {
+                write!(f, "unknown command `{}`, try `help`", command)
+            }
+            ParseError::BadArgument(argument) => write!(f, "bad argument `{}`", argument),
+            ParseError::MissingArgument => write!(f, "missing argument"),
+            ParseError::TooManyArguments => write!(f, "too many arguments"),
+            ParseError::TextTooLong => write!(f, "at most {} characters fit", CELLS),
+            ParseError::Unprintable(c) => write!(f, "cannot show `{}`", c),
+        }
+    }
+}
+
+/// Parses a line without its line ending.
+pub fn parse(line: &str) -> Result<Command, ParseError<'_>> {
+    let line = line.trim_start();
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+    // `show` takes the rest of the line as it is, spaces included
+    if name == "show" {
+        return show(rest).map(Command::Show);
+    }
+
+    let mut arguments = rest.split_whitespace();
+    let argument = arguments.next();
+    if arguments.next().is_some() {
+        return Err(ParseError::TooManyArguments);
+    }
+    let no_argument = |command: Command| match argument {
+        Some(_) => Err(ParseError::TooManyArguments),
+        None => Ok(command),
+    };
+
+    match name {
+        "" => Err(ParseError::Empty),
+        "help" => no_argument(Command::Help),
+        "time" => argument.map(time).transpose().map(Command::Time),
+        "date" => argument.map(date).transpose().map(Command::Date),
+        "hold" => time(argument.ok_or(ParseError::MissingArgument)?).map(Command::Hold),
+        "start" => no_argument(Command::Start),
+        "brightness" => argument
+            .map(|level| level.parse().map_err(|_| ParseError::BadArgument(level)))
+            .transpose()
+            .map(Command::Brightness),
+        "orientation" => argument
+            .map(|name| {
+                ORIENTATION_NAMES
+                    .iter()
+                    .find(|&&(_, known)| known == name)
+                    .map(|&(orientation, _)| orientation)
+                    .ok_or(ParseError::BadArgument(name))
+            })
+            .transpose()
+            .map(Command::Orientation),
+        "transitions" => argument.map(on_off).transpose().map(Command::Transitions),
+        "utc-offset" => argument.map(utc_offset).transpose().map(Command::UtcOffset),
+        "selftest" => no_argument(Command::SelfTest),
+        "settings" => no_argument(Command::Settings),
+        "alarms" => no_argument(Command::Alarms),
+        "bootloader" => no_argument(Command::Bootloader),
+        name => Err(ParseError::UnknownCommand(name)),
+    }
+}
+
+fn time(argument: &str) -> Result<(u8, u8, u8), ParseError<'_>> {
+    let bad = ParseError::BadArgument(argument);
+    let mut fields = argument.split(':').map(|field| field.parse::<u8>());
+
+    let hours = fields.next().ok_or(bad)?.map_err(|_| bad)?;
+    let minutes = fields.next().ok_or(bad)?.map_err(|_| bad)?;
+    let seconds = fields.next().unwrap_or(Ok(0)).map_err(|_| bad)?;
+    if fields.next().is_some() || hours >= 24 || minutes >= 60 || seconds >= 60 {
+        return Err(bad);
+    }
+
+    Ok((hours, minutes, seconds))
+}
+
+fn date(argument: &str) -> Result<(u16, u8, u8), ParseError<'_>> {
+    let bad = ParseError::BadArgument(argument);
+    let mut fields = argument.split('-').map(|field| field.parse::<u16>());
+
+    let year = fields.next().ok_or(bad)?.map_err(|_| bad)?;
+    let month = fields.next().ok_or(bad)?.map_err(|_| bad)?;
+    let day = fields.next().ok_or(bad)?.map_err(|_| bad)?;
+    if fields.next().is_some()
+        || !(BASE_YEAR..BASE_YEAR + RTC_YEARS).contains(&year)
+        || !(1..=12).contains(&month)
+        || day < 1
+        || day > calendar::days_in_month(year, month as u8) as u16
+    {
+        return Err(bad);
+    }
+
+    Ok((year, month as u8, day as u8))
+}
+
+fn on_off(argument: &str) -> Result<bool, ParseError<'_>> {
+    match argument {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(ParseError::BadArgument(argument)),
+    }
+}
+
+/// `+hh:mm` or `-hh:mm` from -12:00 to +14:00, the range of the time zones.
+fn utc_offset(argument: &str) -> Result<i16, ParseError<'_>> {
+    let bad = ParseError::BadArgument(argument);
+    let (sign, rest) = match argument.as_bytes().first() {
+        Some(b'+') => (1, &argument[1..]),
+        Some(b'-') => (-1, &argument[1..]),
+        _ => return Err(bad),
+    };
+    let (hours, minutes) = rest.split_once(':').ok_or(bad)?;
+    let hours = hours.parse::<u8>().map_err(|_| bad)?;
+    let minutes = minutes.parse::<u8>().map_err(|_| bad)?;
+    let offset = sign * (hours as i16 * 60 + minutes as i16);
+    if minutes >= 60 || !(MIN_UTC_OFFSET..=MAX_UTC_OFFSET).contains(&offset) {
+        return Err(bad);
+    }
+
+    Ok(offset)
+}
+
+fn show(text: &str) -> Result<[Symbol; CELLS], ParseError<'_>> {
+    let mut symbols = [seven_segment::to_symbol(0); CELLS];
+    let mut chars = text.chars();
+
+    for symbol in symbols.iter_mut() {
+        match chars.next() {
+            Some(c) => {
+                let bits = seven_segment::from_char(c).ok_or(ParseError::Unprintable(c))?;
+                *symbol = seven_segment::to_symbol(bits);
+            }
+            None if text.is_empty() => return Err(ParseError::MissingArgument),
+            None => break,
+        }
+    }
+    if chars.next().is_some() {
+        return Err(ParseError::TextTooLong);
+    }
+
+    Ok(symbols)
+}
+
+pub fn write_time<W: Write>(out: &mut W, time: &DateTime) -> fmt::Result {
+    write!(
+        out,
+        "{:02}:{:02}:{:02}\r\n",
+        time.hours, time.minutes, time.seconds
+    )
+}
+
+pub fn write_date<W: Write>(out: &mut W, time: &DateTime) -> fmt::Result {
+    write!(
+        out,
+        "{}-{:02}-{:02} {}\r\n",
+        time.year, time.month, time.day, WEEKDAY_NAMES[time.weekday as usize]
+    )
+}
+
+pub fn write_settings<W: Write>(out: &mut W, settings: &Settings) -> fmt::Result {
+    let hour_format = match settings.hour_format {
+        HourFormat::TwentyFour => 24,
+        HourFormat::Twelve => 12,
+    };
+
+    write!(out, "brightness {}\r\n", settings.brightness)?;
+    write!(out, "hour-format {}\r\n", hour_format)?;
+    write!(out, "leading-zero ")?;
+    write_on_off(out, settings.leading_zero)?;
+    write!(out, "transitions ")?;
+    write_on_off(out, settings.transitions)?;
+    write!(out, "orientation ")?;
+    write_orientation(out, settings.orientation)?;
+    write!(out, "utc-offset ")?;
+    write_utc_offset(out, settings.utc_offset)
+}
+
+pub fn write_on_off<W: Write>(out: &mut W, on: bool) -> fmt::Result {
+    write!(out, "{}\r\n", if on { "on" } else { "off" })
+}
+
+pub fn write_utc_offset<W: Write>(out: &mut W, offset: i16) -> fmt::Result {
+    let minutes = offset.unsigned_abs();
+    write!(
+        out,
+        "{}{:02}:{:02}\r\n",
+        if offset < 0 { '-' } else { '+' },
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+pub fn write_orientation<W: Write>(out: &mut W, orientation: Orientation) -> fmt::Result {
+    let (_, name) = ORIENTATION_NAMES
+        .iter()
+        .find(|&&(known, _)| known == orientation)
+        .unwrap();
+    write!(out, "{}\r\n", name)
+}
+
+/// One line per alarm, numbered from 1 like on the display.
+pub fn write_alarms<W: Write>(out: &mut W, alarms: &[Alarm]) -> fmt::Result {
+    for (i, alarm) in alarms.iter().enumerate() {
+        write!(
+            out,
+            "{} {:02}:{:02} {}",
+            i + 1,
+            alarm.hours,
+            alarm.minutes,
+            if alarm.enabled { "on" } else { "off" }
+        )?;
+        for (weekday, name) in WEEKDAY_NAMES.iter().enumerate() {
+            if alarm.weekdays & (1 << weekday) != 0 {
+                write!(out, " {}", &name[..2])?;
+            }
+        }
+        write!(out, "\r\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> [Symbol; CELLS] {
+        let mut symbols = [seven_segment::to_symbol(0); CELLS];
+        for (symbol, c) in symbols.iter_mut().zip(text.chars()) {
+            *symbol = seven_segment::to_symbol(seven_segment::from_char(c).unwrap());
+        }
+        symbols
+    }
+
+    #[test]
+    fn commands_without_arguments() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("selftest"), Ok(Command::SelfTest));
+        assert_eq!(parse("settings"), Ok(Command::Settings));
+        assert_eq!(parse("alarms"), Ok(Command::Alarms));
+        assert_eq!(parse("bootloader"), Ok(Command::Bootloader));
+        assert_eq!(parse("start"), Ok(Command::Start));
+        assert_eq!(parse("  help  "), Ok(Command::Help));
+    }
+
+    #[test]
+    fn extra_arguments() {
+        assert_eq!(parse("help me"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("bootloader now"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("start 1"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("time 12:00 13:00"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("brightness 1 2"), Err(ParseError::TooManyArguments));
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+        assert_eq!(parse("reboot"), Err(ParseError::UnknownCommand("reboot")));
+        assert_eq!(parse("TIME"), Err(ParseError::UnknownCommand("TIME")));
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(parse("time"), Ok(Command::Time(None)));
+        assert_eq!(parse("time 7:05"), Ok(Command::Time(Some((7, 5, 0)))));
+        assert_eq!(
+            parse("time 23:59:59"),
+            Ok(Command::Time(Some((23, 59, 59))))
+        );
+        assert_eq!(parse("time 00:00:00"), Ok(Command::Time(Some((0, 0, 0)))));
+
+        for bad in [
+            "24:00",
+            "12:60",
+            "12:00:60",
+            "12",
+            "12:00:00:00",
+            "a:00",
+            "-1:00",
+            "12:",
+        ] {
+            assert_eq!(
+                parse(&format!("time {}", bad)),
+                Err(ParseError::BadArgument(bad))
+            );
+        }
+    }
+
+    #[test]
+    fn hold() {
+        assert_eq!(parse("hold 12:34:56"), Ok(Command::Hold((12, 34, 56))));
+        assert_eq!(parse("hold 12:34"), Ok(Command::Hold((12, 34, 0))));
+        assert_eq!(parse("hold"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("hold 24:00"), Err(ParseError::BadArgument("24:00")));
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(parse("date"), Ok(Command::Date(None)));
+        assert_eq!(
+            parse("date 2024-02-29"),
+            Ok(Command::Date(Some((2024, 2, 29))))
+        );
+        assert_eq!(
+            parse("date 2000-01-01"),
+            Ok(Command::Date(Some((2000, 1, 1))))
+        );
+        assert_eq!(
+            parse("date 2199-12-31"),
+            Ok(Command::Date(Some((2199, 12, 31))))
+        );
+
+        for bad in [
+            "2023-02-29",
+            "2100-02-29",
+            "2023-04-31",
+            "2023-13-01",
+            "2023-00-01",
+            "2023-01-00",
+            "1999-12-31",
+            "2200-01-01",
+            "2023-01",
+            "2023-01-01-01",
+            "23/01/01",
+        ] {
+            assert_eq!(
+                parse(&format!("date {}", bad)),
+                Err(ParseError::BadArgument(bad))
+            );
+        }
+    }
+
+    #[test]
+    fn brightness() {
+        assert_eq!(parse("brightness"), Ok(Command::Brightness(None)));
+        assert_eq!(parse("brightness 0"), Ok(Command::Brightness(Some(0))));
+        // the firmware checks its own number of levels
+        assert_eq!(parse("brightness 255"), Ok(Command::Brightness(Some(255))));
+        assert_eq!(parse("brightness 256"), Err(ParseError::BadArgument("256")));
+        assert_eq!(parse("brightness -1"), Err(ParseError::BadArgument("-1")));
+        assert_eq!(
+            parse("brightness high"),
+            Err(ParseError::BadArgument("high"))
+        );
+    }
+
+    #[test]
+    fn orientation() {
+        assert_eq!(parse("orientation"), Ok(Command::Orientation(None)));
+        assert_eq!(
+            parse("orientation rotated-180"),
+            Ok(Command::Orientation(Some(Orientation::Rotated180)))
+        );
+        assert_eq!(
+            parse("orientation upside-down"),
+            Err(ParseError::BadArgument("upside-down"))
+        );
+
+        for (orientation, name) in ORIENTATION_NAMES {
+            let mut reply = String::new();
+            write_orientation(&mut reply, orientation).unwrap();
+            assert_eq!(reply, format!("{}\r\n", name));
+            assert_eq!(
+                parse(&format!("orientation {}", name)),
+                Ok(Command::Orientation(Some(orientation)))
+            );
+        }
+    }
+
+    #[test]
+    fn transitions() {
+        assert_eq!(parse("transitions"), Ok(Command::Transitions(None)));
+        assert_eq!(
+            parse("transitions on"),
+            Ok(Command::Transitions(Some(true)))
+        );
+        assert_eq!(
+            parse("transitions off"),
+            Ok(Command::Transitions(Some(false)))
+        );
+        assert_eq!(parse("transitions 1"), Err(ParseError::BadArgument("1")));
+    }
+
+    #[test]
+    fn utc_offset() {
+        assert_eq!(parse("utc-offset"), Ok(Command::UtcOffset(None)));
+        assert_eq!(
+            parse("utc-offset +05:30"),
+            Ok(Command::UtcOffset(Some(330)))
+        );
+        assert_eq!(
+            parse("utc-offset -01:30"),
+            Ok(Command::UtcOffset(Some(-90)))
+        );
+        assert_eq!(parse("utc-offset +00:00"), Ok(Command::UtcOffset(Some(0))));
+        assert_eq!(
+            parse("utc-offset -12:00"),
+            Ok(Command::UtcOffset(Some(-720)))
+        );
+        assert_eq!(
+            parse("utc-offset +14:00"),
+            Ok(Command::UtcOffset(Some(840)))
+        );
+
+        for bad in [
+            "05:30", "+5", "+05:60", "-12:01", "+14:01", "+-1:00", "+05:3a", "+",
+        ] {
+            assert_eq!(
+                parse(&format!("utc-offset {}", bad)),
+                Err(ParseError::BadArgument(bad))
+            );
+        }
+
+        for offset in [-720, -90, 0, 330, 840] {
+            let mut reply = String::new();
+            write_utc_offset(&mut reply, offset).unwrap();
+            let argument = reply.trim_end();
+            assert_eq!(
+                parse(&format!("utc-offset {}", argument)),
+                Ok(Command::UtcOffset(Some(offset)))
+            );
+        }
+    }
+
+    #[test]
+    fn show() {
+        assert_eq!(parse("show 12ab"), Ok(Command::Show(text("12ab"))));
+        assert_eq!(parse("show hi"), Ok(Command::Show(text("hi"))));
+        // spaces are part of the text
+        assert_eq!(parse("show  1 2"), Ok(Command::Show(text(" 1 2"))));
+        assert_eq!(parse("show -"), Ok(Command::Show(text("-"))));
+
+        assert_eq!(parse("show"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("show "), Err(ParseError::MissingArgument));
+        assert_eq!(parse("show 12345"), Err(ParseError::TextTooLong));
+        assert_eq!(parse("show 1 2 3"), Err(ParseError::TextTooLong));
+        assert_eq!(parse("show 1?"), Err(ParseError::Unprintable('?')));
+    }
+
+    #[test]
+    fn replies() {
+        let time = DateTime::new(2024, 3, 5, 7, 8, 9);
+        let mut reply = String::new();
+        write_time(&mut reply, &time).unwrap();
+        write_date(&mut reply, &time).unwrap();
+        assert_eq!(reply, "07:08:09\r\n2024-03-05 Tuesday\r\n");
+
+        let settings = Settings {
+            utc_offset: -90,
+            ..Settings::DEFAULT
+        };
+        let mut reply = String::new();
+        write_settings(&mut reply, &settings).unwrap();
+        assert!(reply.ends_with("utc-offset -01:30\r\n"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Spatial queries over the segments of a cell.
+//!
+//! Segments are ordered by their centroid. The queries return segment
+//! indices, so the results can be used with `draw_segment` directly.
+
+use crate::{Point, SEGMENTS};
+
+pub type Order = [usize; SEGMENTS.len()];
+
+/// Segments from left to right.
+pub fn by_x() -> Order {
+    sorted_by(|segment| SEGMENTS[segment].centroid.x as i32)
+}
+
+/// Segments from top to bottom.
+pub fn by_y() -> Order {
+    sorted_by(|segment| SEGMENTS[segment].centroid.y as i32)
+}
+
+/// Segments from the closest to `point` to the farthest.
+pub fn by_distance(point: Point) -> Order {
+    sorted_by(|segment| distance_squared(SEGMENTS[segment].centroid, point) as i32)
+}
+
+/// The segment whose outline contains `point`, if any.
+pub fn segment_at(point: Point) -> Option<usize> {
+    SEGMENTS
+        .iter()
+        .position(|segment| segment.bounds.contains(point) && contains(segment.outline, point))
+}
+
+pub fn distance_squared(a: Point, b: Point) -> u32 {
+    let dx = a.x as i32 - b.x as i32;
+    let dy = a.y as i32 - b.y as i32;
+    (dx * dx + dy * dy) as u32
+}
+
+// even-odd rule
+pub(crate) fn contains(outline: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    let mut previous = outline[outline.len() - 1];
+
+    for &current in outline {
+        if (current.y > point.y) != (previous.y > point.y) {
+            let (x0, y0) = (current.x as i32, current.y as i32);
+            let (x1, y1) = (previous.x as i32, previous.y as i32);
+            let crossing = x0 + (x1 - x0) * (point.y as i32 - y0) / (y1 - y0);
+            if (point.x as i32) < crossing {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+
+    inside
+}
+
+// insertion sort, stable so ties keep the segment order
+fn sorted_by(key: impl Fn(usize) -> i32) -> Order {
+    let mut order = [0; SEGMENTS.len()];
+    for (i, segment) in order.iter_mut().enumerate() {
+        *segment = i;
+    }
+
+    for i in 1..order.len() {
+        let mut j = i;
+        while j > 0 && key(order[j - 1]) > key(order[j]) {
+            order.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+
+    order
+}
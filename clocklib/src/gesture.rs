@@ -0,0 +1,642 @@
+//! Turning button levels into gestures.
+//!
+//! [`Recognizer`] is a state machine fed with the levels of the buttons and
+//! a millisecond timestamp, see [`Recognizer::update`]. It debounces the
+//! levels and reports:
+//!
+//! - a short press on release, delayed by the double click time on buttons
+//!   taking double clicks,
+//! - a double click on the second press,
+//! - a long press once the button was held for the long press time,
+//! - repeats while a button taking them is held, coming faster the longer
+//!   it is held,
+//! - both buttons pressed together, which swallows everything else until
+//!   both are released.
+
+use heapless::Deque;
+
+pub const BUTTONS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    Short(usize),
+    Double(usize),
+    Long(usize),
+    Repeat(usize),
+    /// All buttons pressed together.
+    Both,
+}
+
+/// Times in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    /// How long a level has to be steady to count.
+    pub debounce: u64,
+    pub long: u64,
+    /// The longest gap between the clicks of a double click.
+    pub double_click: u64,
+    /// The longest gap between the presses of both buttons.
+    pub both: u64,
+    /// Holding before the first repeat.
+    pub repeat_delay: u64,
+    /// Time between the first repeats, halved every `repeats_per_step`
+    /// repeats down to `repeat_min`.
+    pub repeat: u64,
+    pub repeat_min: u64,
+    pub repeats_per_step: u16,
+}
+
+impl Timing {
+    pub const DEFAULT: Timing = Timing {
+        debounce: 50,
+        long: 3000,
+        double_click: 300,
+        both: 150,
+        repeat_delay: 600,
+        repeat: 250,
+        repeat_min: 40,
+        repeats_per_step: 5,
+    };
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::DEFAULT
+    }
+}
+
+/// The gestures a button takes besides short and long presses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub double_click: bool,
+    pub repeat: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Released,
+    Pressed {
+        since: u64,
+        repeats: u16,
+        next_repeat: u64,
+        long_sent: bool,
+    },
+    /// Released after a short press, a second press makes a double click.
+    WaitingForDouble {
+        released: u64,
+    },
+    /// Part of a gesture that is already reported, ignored until released.
+    Ignored,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Button {
+    options: Options,
+    // last level seen and since when
+    level: bool,
+    level_since: u64,
+    // debounced level
+    pressed: bool,
+    state: State,
+}
+
+pub struct Recognizer {
+    timing: Timing,
+    buttons: [Button; BUTTONS],
+    // both buttons were pressed and not all released yet
+    both_held: bool,
+    gestures: Deque<Gesture, 4>,
+}
+
+impl Recognizer {
+    pub fn new(timing: Timing, options: [Options; BUTTONS]) -> Recognizer {
+        Recognizer {
+            timing,
+            buttons: options.map(|options| Button {
+                options,
+                level: false,
+                level_since: 0,
+                pressed: false,
+                state: State::Released,
+            }),
+            both_held: false,
+            gestures: Deque::new(),
+        }
+    }
+
+    /// Changes the gestures `button` takes, for example when a view starts
+    /// or stops using repeats. A press going on keeps what it reported.
+    pub fn set_options(&mut self, button: usize, options: Options) {
+        self.buttons[button].options = options;
+    }
+
+    /// Takes the levels of the buttons, true while pressed, at `now` and
+    /// returns the next gesture. Call it on every change of a level, at
+    /// [`deadline`](Recognizer::deadline) and again while it returns
+    /// gestures.
+    pub fn update(&mut self, pressed: [bool; BUTTONS], now: u64) -> Option<Gesture> {
+        for (i, &level) in pressed.iter().enumerate() {
+            let button = &mut self.buttons[i];
+            if level != button.level {
+                button.level = level;
+                button.level_since = now;
+            }
+            // the level changed when the bouncing started
+            if button.level != button.pressed && now >= button.level_since + self.timing.debounce {
+                button.pressed = button.level;
+                let at = button.level_since;
+                if button.pressed {
+                    self.press(i, at);
+                } else {
+                    self.release(i, at);
+                }
+            }
+        }
+
+        if self.buttons.iter().all(|button| !button.pressed) {
+            self.both_held = false;
+        }
+
+        for i in 0..BUTTONS {
+            self.timeouts(i, now);
+        }
+
+        self.gestures.pop_front()
+    }
+
+    /// When to call [`update`](Recognizer::update) at the latest if no
+    /// level changes.
+    pub fn deadline(&self) -> Option<u64> {
+        let timing = &self.timing;
+        self.buttons
+            .iter()
+            .filter_map(|button| {
+                // timeouts wait for the level to settle, see `timeouts`
+                if button.level != button.pressed {
+                    return Some(button.level_since + timing.debounce);
+                }
+                match button.state {
+                    State::Pressed {
+                        since,
+                        next_repeat,
+                        long_sent,
+                        ..
+                    } => {
+                        let long = (!long_sent).then_some(since + timing.long);
+                        earliest(long, button.options.repeat.then_some(next_repeat))
+                    }
+                    State::WaitingForDouble { released } => Some(released + timing.double_click),
+                    State::Released | State::Ignored => None,
+                }
+            })
+            .min()
+    }
+
+    fn press(&mut self, i: usize, at: u64) {
+        if self.both_held {
+            self.buttons[i].state = State::Ignored;
+            return;
+        }
+
+        // the other buttons held shortly and not used for anything yet
+        let both = self.buttons.iter().enumerate().all(|(j, other)| {
+            j == i
+                || matches!(other.state, State::Pressed { since, repeats: 0, long_sent: false, .. }
+                    if at <= since + self.timing.both)
+        });
+
+        if both {
+            for button in self.buttons.iter_mut() {
+                button.state = State::Ignored;
+            }
+            self.both_held = true;
+            self.emit(Gesture::Both);
+            return;
+        }
+
+        let button = &mut self.buttons[i];
+        if let State::WaitingForDouble { released } = button.state {
+            if at < released + self.timing.double_click {
+                button.state = State::Ignored;
+                self.emit(Gesture::Double(i));
+                return;
+            }
+            // pressed again too late, noticed only once debounced
+            self.emit(Gesture::Short(i));
+        }
+
+        self.buttons[i].state = State::Pressed {
+            since: at,
+            repeats: 0,
+            next_repeat: at + self.timing.repeat_delay,
+            long_sent: false,
+        };
+    }
+
+    fn release(&mut self, i: usize, at: u64) {
+        let button = &mut self.buttons[i];
+        match button.state {
+            // a press that did nothing yet
+            State::Pressed {
+                repeats: 0,
+                long_sent: false,
+                ..
+            } => {
+                if button.options.double_click {
+                    button.state = State::WaitingForDouble { released: at };
+                } else {
+                    button.state = State::Released;
+                    self.emit(Gesture::Short(i));
+                }
+            }
+            _ => button.state = State::Released,
+        }
+    }
+
+    fn timeouts(&mut self, i: usize, now: u64) {
+        let timing = self.timing;
+        let button = &mut self.buttons[i];
+
+        // a press or release that is still bouncing started before the
+        // timeouts, they come once it is debounced and handled
+        if button.level != button.pressed {
+            return;
+        }
+
+        match &mut button.state {
+            State::Pressed {
+                since,
+                repeats,
+                next_repeat,
+                long_sent,
+            } => {
+                let long = !*long_sent && now >= *since + timing.long;
+                let repeat = button.options.repeat && now >= *next_repeat;
+                if long {
+                    *long_sent = true;
+                }
+                if repeat {
+                    *repeats = repeats.saturating_add(1);
+                    let step = (*repeats / timing.repeats_per_step.max(1)).min(63);
+                    *next_repeat += (timing.repeat >> step).max(timing.repeat_min);
+                }
+
+                if repeat {
+                    self.emit(Gesture::Repeat(i));
+                }
+                if long {
+                    self.emit(Gesture::Long(i));
+                }
+            }
+            State::WaitingForDouble { released } => {
+                if now >= *released + timing.double_click {
+                    button.state = State::Released;
+                    self.emit(Gesture::Short(i));
+                }
+            }
+            State::Released | State::Ignored => {}
+        }
+    }
+
+    fn emit(&mut self, gesture: Gesture) {
+        // the oldest gesture makes room, the queue only fills up when
+        // updates stop coming
+        if self.gestures.is_full() {
+            self.gestures.pop_front();
+        }
+        self.gestures.push_back(gesture).ok();
+    }
+}
+
+fn earliest(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SET: usize = 0;
+    const ADJUST: usize = 1;
+    const TIMING: Timing = Timing::DEFAULT;
+
+    fn recognizer() -> Recognizer {
+        Recognizer::new(
+            TIMING,
+            [
+                Options {
+                    double_click: true,
+                    repeat: false,
+                },
+                Options {
+                    double_click: false,
+                    repeat: true,
+                },
+            ],
+        )
+    }
+
+    /// Feeds level changes at the given times, waking up at every deadline
+    /// in between like the firmware does, and collects the gestures with
+    /// the time they came out.
+    fn run(
+        recognizer: &mut Recognizer,
+        changes: &[(u64, [bool; BUTTONS])],
+        end: u64,
+    ) -> Vec<(u64, Gesture)> {
+        let mut gestures = Vec::new();
+        let mut levels = [false; BUTTONS];
+        let mut changes = changes.iter().peekable();
+        let mut now = 0;
+
+        loop {
+            while let Some(gesture) = recognizer.update(levels, now) {
+                gestures.push((now, gesture));
+            }
+
+            let change = changes.peek().map(|&&(at, _)| at);
+            let next = match (change, recognizer.deadline()) {
+                (Some(change), Some(deadline)) => change.min(deadline),
+                (change, deadline) => match change.or(deadline) {
+                    Some(next) => next,
+                    None => break,
+                },
+            };
+            if next > end {
+                break;
+            }
+            assert!(next >= now, "deadline {} in the past at {}", next, now);
+            now = next;
+            if change == Some(now) {
+                levels = changes.next().unwrap().1;
+            }
+        }
+
+        gestures
+    }
+
+    fn press(at: u64, button: usize) -> (u64, [bool; BUTTONS]) {
+        let mut levels = [false; BUTTONS];
+        levels[button] = true;
+        (at, levels)
+    }
+
+    fn release(at: u64) -> (u64, [bool; BUTTONS]) {
+        (at, [false; BUTTONS])
+    }
+
+    #[test]
+    fn short_press() {
+        let gestures = run(
+            &mut recognizer(),
+            &[press(100, ADJUST), release(300)],
+            10_000,
+        );
+        assert_eq!(gestures, [(350, Gesture::Short(ADJUST))]);
+    }
+
+    #[test]
+    fn short_press_waits_for_a_double_click() {
+        let gestures = run(&mut recognizer(), &[press(100, SET), release(300)], 10_000);
+        assert_eq!(gestures, [(300 + TIMING.double_click, Gesture::Short(SET))]);
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        let gestures = run(
+            &mut recognizer(),
+            &[
+                press(100, ADJUST),
+                release(110),
+                press(120, ADJUST),
+                release(130),
+                press(140, ADJUST),
+                release(400),
+                press(410, ADJUST),
+                release(420),
+            ],
+            10_000,
+        );
+        assert_eq!(gestures, [(470, Gesture::Short(ADJUST))]);
+    }
+
+    #[test]
+    fn glitches_shorter_than_the_debounce_time_are_no_press() {
+        let gestures = run(
+            &mut recognizer(),
+            &[press(100, ADJUST), release(140)],
+            10_000,
+        );
+        assert_eq!(gestures, []);
+    }
+
+    #[test]
+    fn double_click() {
+        let gestures = run(
+            &mut recognizer(),
+            &[press(100, SET), release(200), press(400, SET), release(500)],
+            10_000,
+        );
+        assert_eq!(gestures, [(450, Gesture::Double(SET))]);
+    }
+
+    #[test]
+    fn clicks_too_far_apart_are_two_short_presses() {
+        let gestures = run(
+            &mut recognizer(),
+            &[press(100, SET), release(200), press(600, SET), release(700)],
+            10_000,
+        );
+        assert_eq!(
+            gestures,
+            [(500, Gesture::Short(SET)), (1000, Gesture::Short(SET))]
+        );
+    }
+
+    #[test]
+    fn double_click_window() {
+        let last_chance = 200 + TIMING.double_click - 1;
+        let gestures = run(
+            &mut recognizer(),
+            &[
+                press(100, SET),
+                release(200),
+                press(last_chance, SET),
+                release(last_chance + 100),
+            ],
+            10_000,
+        );
+        assert_eq!(gestures, [(last_chance + 50, Gesture::Double(SET))]);
+
+        let too_late = last_chance + 1;
+        let gestures = run(
+            &mut recognizer(),
+            &[
+                press(100, SET),
+                release(200),
+                press(too_late, SET),
+                release(too_late + 100),
+            ],
+            10_000,
+        );
+        // the first click is reported once the second press is debounced
+        assert_eq!(
+            gestures,
+            [
+                (too_late + 50, Gesture::Short(SET)),
+                (too_late + 100 + TIMING.double_click, Gesture::Short(SET)),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_click_seen_late() {
+        // the second press started in time, but was only debounced after
+        // the window closed
+        let mut recognizer = recognizer();
+        for (now, level) in [
+            (100, true),
+            (150, true),
+            (200, false),
+            (250, false),
+            (480, true),
+        ] {
+            assert_eq!(recognizer.update([level, false], now), None);
+        }
+        assert_eq!(
+            recognizer.update([true, false], 700),
+            Some(Gesture::Double(SET))
+        );
+        assert_eq!(recognizer.update([true, false], 701), None);
+    }
+
+    #[test]
+    fn long_press() {
+        let gestures = run(&mut recognizer(), &[press(100, SET), release(5000)], 10_000);
+        assert_eq!(gestures, [(100 + TIMING.long, Gesture::Long(SET))]);
+    }
+
+    #[test]
+    fn both_buttons() {
+        let gestures = run(
+            &mut recognizer(),
+            &[
+                press(100, SET),
+                (200, [true, true]),
+                // letting go of one and pressing it again does nothing
+                press(1000, ADJUST),
+                (1200, [true, true]),
+                release(5000),
+            ],
+            10_000,
+        );
+        assert_eq!(gestures, [(250, Gesture::Both)]);
+    }
+
+    #[test]
+    fn buttons_pressed_too_far_apart_are_not_both() {
+        let gestures = run(
+            &mut recognizer(),
+            &[press(100, SET), (400, [true, true]), release(500)],
+            10_000,
+        );
+        assert_eq!(
+            gestures,
+            [
+                (550, Gesture::Short(ADJUST)),
+                (500 + TIMING.double_click, Gesture::Short(SET)),
+            ]
+        );
+    }
+
+    #[test]
+    fn holding_repeats_faster_and_faster() {
+        let gestures = run(
+            &mut recognizer(),
+            &[press(0, ADJUST), release(10_000)],
+            20_000,
+        );
+
+        let repeats: Vec<u64> = gestures
+            .iter()
+            .filter(|(_, gesture)| *gesture == Gesture::Repeat(ADJUST))
+            .map(|&(at, _)| at)
+            .collect();
+        assert_eq!(repeats[0], TIMING.repeat_delay);
+
+        let intervals: Vec<u64> = repeats.windows(2).map(|at| at[1] - at[0]).collect();
+        let step = TIMING.repeats_per_step as usize;
+        assert!(intervals[..step - 1]
+            .iter()
+            .all(|&interval| interval == TIMING.repeat));
+        assert!(intervals.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(*intervals.last().unwrap(), TIMING.repeat_min);
+        assert!(intervals
+            .iter()
+            .all(|&interval| interval >= TIMING.repeat_min));
+
+        // still long pressed once, without stopping the repeats
+        assert_eq!(
+            gestures
+                .iter()
+                .filter(|(_, gesture)| *gesture == Gesture::Long(ADJUST))
+                .collect::<Vec<_>>(),
+            [&(TIMING.long, Gesture::Long(ADJUST))]
+        );
+        assert!(repeats.iter().any(|&at| at > TIMING.long));
+        // nothing after the release
+        assert!(gestures.iter().all(|&(at, _)| at < 10_000));
+    }
+
+    #[test]
+    fn holding_without_repeat_only_long_presses() {
+        let gestures = run(&mut recognizer(), &[press(0, SET), release(10_000)], 20_000);
+        assert_eq!(gestures, [(TIMING.long, Gesture::Long(SET))]);
+    }
+
+    #[test]
+    fn holding_without_repeat_is_short_until_long() {
+        let mut recognizer = recognizer();
+        recognizer.set_options(ADJUST, Options::default());
+        let gestures = run(
+            &mut recognizer,
+            &[press(0, ADJUST), release(TIMING.long - 100)],
+            10_000,
+        );
+        assert_eq!(gestures, [(TIMING.long - 50, Gesture::Short(ADJUST))]);
+    }
+
+    #[test]
+    fn options_change_between_presses() {
+        let mut recognizer = recognizer();
+        let repeat = Options {
+            double_click: false,
+            repeat: true,
+        };
+        recognizer.set_options(ADJUST, Options::default());
+        assert_eq!(
+            run(&mut recognizer, &[press(0, ADJUST), release(1000)], 2000),
+            [(1050, Gesture::Short(ADJUST))]
+        );
+
+        recognizer.set_options(ADJUST, repeat);
+        let gestures = run(&mut recognizer, &[press(0, ADJUST), release(1000)], 2000);
+        assert_eq!(gestures[0], (TIMING.repeat_delay, Gesture::Repeat(ADJUST)));
+        assert!(gestures
+            .iter()
+            .all(|&(_, gesture)| gesture == Gesture::Repeat(ADJUST)));
+    }
+
+    #[test]
+    fn no_deadline_when_idle() {
+        let mut recognizer = recognizer();
+        assert_eq!(recognizer.update([false, false], 1000), None);
+        assert_eq!(recognizer.deadline(), None);
+
+        recognizer.update([true, false], 2000);
+        assert_eq!(recognizer.deadline(), Some(2000 + TIMING.debounce));
+    }
+}
@@ -0,0 +1,740 @@
+use core::fmt;
+
+use crate::SEGMENTS;
+
+/// Declares a [`Symbol`] at compile time.
+///
+/// A symbol is either drawn as ASCII art, one `#` (lit) or `.` (dark) per
+/// segment in segment order, or given as a list of lit segment indices.
+/// Whitespace in the art is ignored, so it can be laid out like the cell,
+/// with the indices counted from 0:
+///
+/// ```text
+///     0  1  2  3
+///  4  5  6  7  8  9
+/// 10 11 12 13 14 15
+///    16 17 18 19
+/// 20 21       22 23
+///    24 25 26 27
+/// 28 29 30 31 32 33
+/// 34 35 36 37 38 39
+///    40 41 42 43
+/// ```
+///
+/// ```
+/// use clocklib::glyph;
+///
+/// let dash = glyph!(
+///     "  . . . .  "
+///     ". . . . . ."
+///     ". . . . . ."
+///     "  . . . .  "
+///     ". #     # ."
+///     "  . . . .  "
+///     ". . . . . ."
+///     ". . . . . ."
+///     "  . . . .  "
+/// );
+/// assert_eq!(dash, glyph!([21, 22]));
+/// ```
+///
+/// Art with the wrong number of segments and segment indices outside the
+/// cell are rejected when compiling.
+#[macro_export]
+macro_rules! glyph {
+    ([$($segment:expr),* $(,)?]) => {{
+        const SYMBOL: $crate::Symbol = $crate::Symbol::from_segments(&[$($segment),*]);
+        SYMBOL
+    }};
+    ($($row:literal)+) => {{
+        const SYMBOL: $crate::Symbol = $crate::Symbol::from_art(concat!($($row),+));
+        SYMBOL
+    }};
+}
+
+/// The lit segments of one cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub(crate) mask: [u8; 6],
+}
+
+impl Symbol {
+    /// Parses ASCII art, see [`glyph!`].
+    pub const fn from_art(art: &str) -> Symbol {
+        let art = art.as_bytes();
+        let mut mask = [0; 6];
+        let mut segment = 0;
+        let mut i = 0;
+
+        while i < art.len() {
+            match art[i] {
+                b'#' | b'.' => {
+                    assert!(segment < SEGMENTS.len(), "glyph has too many segments");
+                    if art[i] == b'#' {
+                        mask[segment / 8] |= 1 << (segment % 8);
+                    }
+                    segment += 1;
+                }
+                b' ' | b'\t' | b'\n' | b'\r' => {}
+                _ => panic!("glyph art may only contain `#`, `.` and whitespace"),
+            }
+            i += 1;
+        }
+
+        assert!(segment == SEGMENTS.len(), "glyph has too few segments");
+        Symbol { mask }
+    }
+
+    /// Builds a symbol from the indices of its lit segments.
+    pub const fn from_segments(segments: &[usize]) -> Symbol {
+        let mut mask = [0; 6];
+        let mut i = 0;
+
+        while i < segments.len() {
+            let segment = segments[i];
+            assert!(segment < SEGMENTS.len(), "segment index out of range");
+            mask[segment / 8] |= 1 << (segment % 8);
+            i += 1;
+        }
+
+        Symbol { mask }
+    }
+
+    pub const fn from_mask(mask: [u8; 6]) -> Symbol {
+        Symbol { mask }
+    }
+
+    /// The lit segments as a bit mask, least significant bit first, like the
+    /// masks printed by the old browser editor.
+    pub const fn mask(&self) -> [u8; 6] {
+        self.mask
+    }
+
+    pub const fn is_lit(&self, segment_id: usize) -> bool {
+        self.mask[segment_id / 8] & (1 << (segment_id % 8)) != 0
+    }
+
+    /// Writes the symbol as the rows of a [`glyph!`] invocation, one quoted
+    /// row per line.
+    pub fn write_art<W: fmt::Write>(&self, out: &mut W, indent: &str) -> fmt::Result {
+        let mut segment = 0;
+
+        for columns in ART_LAYOUT {
+            let mut row = [b' '; 11];
+            for &column in columns {
+                row[column * 2] = if self.is_lit(segment) { b'#' } else { b'.' };
+                segment += 1;
+            }
+            // the row only contains ASCII
+            let row = core::str::from_utf8(&row).unwrap();
+            writeln!(out, "{}\"{}\"", indent, row)?;
+        }
+
+        Ok(())
+    }
+}
+
+// columns of the segments in each row of the art, see `glyph!`
+const ART_LAYOUT: [&[usize]; 9] = [
+    &[1, 2, 3, 4],
+    &[0, 1, 2, 3, 4, 5],
+    &[0, 1, 2, 3, 4, 5],
+    &[1, 2, 3, 4],
+    &[0, 1, 4, 5],
+    &[1, 2, 3, 4],
+    &[0, 1, 2, 3, 4, 5],
+    &[0, 1, 2, 3, 4, 5],
+    &[1, 2, 3, 4],
+];
+
+pub const DIGITS: [Symbol; 10] = [
+    // 0
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // 1
+    glyph!(
+        "  # # # .  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+        ". .     . ."
+        "  . # # .  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+    ),
+    // 2
+    glyph!(
+        "  # # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  . # # #  "
+        ". #     # ."
+        "  # # # .  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # #  "
+    ),
+    // 3
+    glyph!(
+        "  # # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  . # # #  "
+        ". #     # ."
+        "  . # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  # # # #  "
+    ),
+    // 4
+    glyph!(
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     # ."
+        "  . # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  . . . #  "
+    ),
+    // 5
+    glyph!(
+        "  # # # #  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # .  "
+        ". #     # ."
+        "  . # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  # # # #  "
+    ),
+    // 6
+    glyph!(
+        "  # # # #  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # .  "
+        ". #     # ."
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // 7
+    glyph!(
+        "  # # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  . . # #  "
+        ". .     # ."
+        "  . # # .  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+    ),
+    // 8
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     # ."
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // 9
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     # ."
+        "  . # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  # # # #  "
+    ),
+];
+
+pub const PROGRESS_LTR: [Symbol; 6] = [
+    glyph!(
+        "  . . . .  "
+        "# . . . . ."
+        "# . . . . ."
+        "  . . . .  "
+        "# .     . ."
+        "  . . . .  "
+        "# . . . . ."
+        "# . . . . ."
+        "  . . . .  "
+    ),
+    glyph!(
+        "  . . . .  "
+        "# # . . . ."
+        "# # . . . ."
+        "  # . . .  "
+        "# #     . ."
+        "  # . . .  "
+        "# # . . . ."
+        "# # . . . ."
+        "  . . . .  "
+    ),
+    glyph!(
+        "  . . . .  "
+        "# # # . . ."
+        "# # # . . ."
+        "  # # . .  "
+        "# #     . ."
+        "  # # . .  "
+        "# # # . . ."
+        "# # # . . ."
+        "  . . . .  "
+    ),
+    glyph!(
+        "  . . . .  "
+        "# # # # . ."
+        "# # # # . ."
+        "  # # # .  "
+        "# #     . ."
+        "  # # # .  "
+        "# # # # . ."
+        "# # # # . ."
+        "  . . . .  "
+    ),
+    glyph!(
+        "  . . . .  "
+        "# # # # # ."
+        "# # # # # ."
+        "  # # # #  "
+        "# #     # ."
+        "  # # # #  "
+        "# # # # # ."
+        "# # # # # ."
+        "  . . . .  "
+    ),
+    glyph!(
+        "  . . . .  "
+        "# # # # # #"
+        "# # # # # #"
+        "  # # # #  "
+        "# #     # #"
+        "  # # # #  "
+        "# # # # # #"
+        "# # # # # #"
+        "  . . . .  "
+    ),
+];
+
+/// A bar over `N` cells made of [`PROGRESS_LTR`], filled to `value` out of
+/// `max`. Any value above zero lights at least one column.
+pub fn progress_bar<const N: usize>(value: u32, max: u32) -> [Symbol; N] {
+    let steps = PROGRESS_LTR.len() as u32;
+    let columns = (N as u32) * steps;
+    let filled = if max == 0 {
+        0
+    } else {
+        ((value.min(max) as u64 * columns as u64 + max as u64 - 1) / max as u64) as u32
+    };
+
+    let mut bar = [Symbol::from_mask([0; 6]); N];
+    for (i, cell) in bar.iter_mut().enumerate() {
+        let lit = filled.saturating_sub(i as u32 * steps).min(steps);
+        if lit > 0 {
+            *cell = PROGRESS_LTR[lit as usize - 1];
+        }
+    }
+    bar
+}
+
+pub const CH_LTR: [Symbol; 2] = [
+    // C
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . . ."
+        "  # . . .  "
+        ". #     . ."
+        "  # . . .  "
+        ". # . . . ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // H
+    glyph!(
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     # ."
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+    ),
+];
+
+/// Two letter weekday names from Sunday, two glyphs per day.
+pub const WEEKDAYS: [Symbol; 14] = [
+    // S
+    glyph!(
+        "  # # # #  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # .  "
+        ". .     # ."
+        "  . # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  # # # #  "
+    ),
+    // U
+    glyph!(
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // M
+    glyph!(
+        "  # . . #  "
+        ". # # # # ."
+        ". # # # # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+    ),
+    // O
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // T
+    glyph!(
+        "  # # # #  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+        ". .     . ."
+        "  . # # .  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+    ),
+    // U
+    glyph!(
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+    ),
+    // W
+    glyph!(
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # # # # ."
+        ". # # # # ."
+        "  # . . #  "
+    ),
+    // E
+    glyph!(
+        "  # # # #  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # .  "
+        ". #     . ."
+        "  # # # .  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # #  "
+    ),
+    // T
+    glyph!(
+        "  # # # #  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+        ". .     . ."
+        "  . # # .  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+    ),
+    // H
+    glyph!(
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     # ."
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+    ),
+    // F
+    glyph!(
+        "  # # # #  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # .  "
+        ". #     . ."
+        "  # . . .  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # . . .  "
+    ),
+    // R
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     . ."
+        "  # # . .  "
+        ". # . # . ."
+        ". # . . # ."
+        "  # . . #  "
+    ),
+    // S
+    glyph!(
+        "  # # # #  "
+        ". # . . . ."
+        ". # . . . ."
+        "  # # # .  "
+        ". .     # ."
+        "  . # # #  "
+        ". . . . # ."
+        ". . . . # ."
+        "  # # # #  "
+    ),
+    // A
+    glyph!(
+        "  # # # #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # # # #  "
+        ". #     # ."
+        "  # . . #  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+    ),
+];
+
+/// Status icons, in the order of [`Icon`].
+pub const ICONS: [Symbol; 9] = [
+    // bell
+    glyph!(
+        "  . # # .  "
+        ". # # # # ."
+        ". # # # # ."
+        "  # # # #  "
+        ". #     # ."
+        "  # # # #  "
+        "# # # # # #"
+        ". . . . . ."
+        "  . # # .  "
+    ),
+    // hourglass
+    glyph!(
+        "  # # # #  "
+        ". # # # # ."
+        ". . # # . ."
+        "  . # # .  "
+        ". .     . ."
+        "  . # # .  "
+        ". . # # . ."
+        ". # # # # ."
+        "  # # # #  "
+    ),
+    // sun
+    glyph!(
+        "  # . . #  "
+        ". . # # . ."
+        "# # # # # #"
+        "  # # # #  "
+        "# #     # #"
+        "  # # # #  "
+        "# # # # # #"
+        ". . # # . ."
+        "  # . . #  "
+    ),
+    // moon
+    glyph!(
+        "  . # # #  "
+        ". # # . . ."
+        "# # . . . ."
+        "  # . . .  "
+        "# #     . ."
+        "  # . . .  "
+        "# # . . . ."
+        ". # # . . ."
+        "  . # # #  "
+    ),
+    // usb
+    glyph!(
+        "  . # # .  "
+        ". . # # . ."
+        ". # # # # ."
+        "  # # # #  "
+        ". #     # ."
+        "  # # # #  "
+        ". . # # . ."
+        ". . # # . ."
+        "  . # # .  "
+    ),
+    // battery
+    glyph!(
+        "  . # # .  "
+        ". # # # # ."
+        ". # . . # ."
+        "  # . . #  "
+        ". #     # ."
+        "  # # # #  "
+        ". # # # # ."
+        ". # # # # ."
+        "  # # # #  "
+    ),
+    // warning
+    glyph!(
+        "  . # # .  "
+        ". . # # . ."
+        ". # . . # ."
+        "  . # # .  "
+        ". #     # ."
+        "  . # # .  "
+        "# . . . . #"
+        "# # # # # #"
+        "  . . . .  "
+    ),
+    // check
+    glyph!(
+        "  . . . .  "
+        ". . . . . #"
+        ". . . . # ."
+        "  . . . #  "
+        "# .     # ."
+        "  # . # .  "
+        ". # # . . ."
+        ". . # . . ."
+        "  . . . .  "
+    ),
+    // lock
+    glyph!(
+        "  . # # .  "
+        ". # . . # ."
+        ". # . . # ."
+        "  # . . #  "
+        "# #     # #"
+        "  # # # #  "
+        "# # . . # #"
+        "# # # # # #"
+        "  # # # #  "
+    ),
+];
+
+/// A status icon, see [`ICONS`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Icon {
+    /// Alarm bell.
+    Bell,
+    /// Timer hourglass.
+    Hourglass,
+    Sun,
+    Moon,
+    /// USB plug.
+    Usb,
+    /// Half charged battery.
+    Battery,
+    /// Warning triangle with an exclamation mark.
+    Warning,
+    /// Checkmark.
+    Check,
+    /// Padlock.
+    Lock,
+}
+
+impl Icon {
+    pub const ALL: [Icon; ICONS.len()] = [
+        Icon::Bell,
+        Icon::Hourglass,
+        Icon::Sun,
+        Icon::Moon,
+        Icon::Usb,
+        Icon::Battery,
+        Icon::Warning,
+        Icon::Check,
+        Icon::Lock,
+    ];
+
+    pub const fn symbol(self) -> Symbol {
+        ICONS[self as usize]
+    }
+
+    /// The lower case name, also used as label in [`ICONS`].
+    pub const fn name(self) -> &'static str {
+        match self {
+            Icon::Bell => "bell",
+            Icon::Hourglass => "hourglass",
+            Icon::Sun => "sun",
+            Icon::Moon => "moon",
+            Icon::Usb => "usb",
+            Icon::Battery => "battery",
+            Icon::Warning => "warning",
+            Icon::Check => "check",
+            Icon::Lock => "lock",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Icon> {
+        Icon::ALL.iter().copied().find(|icon| icon.name() == name)
+    }
+}
+
+/// Every glyph table by name, for the host tools. Animations refer to the
+/// tables by position, so new tables go at the end.
+pub const TABLES: [(&str, &[Symbol]); 5] = [
+    ("DIGITS", &DIGITS),
+    ("PROGRESS_LTR", &PROGRESS_LTR),
+    ("CH_LTR", &CH_LTR),
+    ("ICONS", &ICONS),
+    ("WEEKDAYS", &WEEKDAYS),
+];
@@ -1,154 +1,128 @@
-#![no_std]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 use bitvec::prelude::*;
 use core::fmt::Debug;
 use embedded_hal::blocking::i2c;
 use is31fl3731_driver::{Error, IS31FL3731};
 
+pub mod alarm;
+pub mod animation;
+pub mod calendar;
+pub mod calibration;
+pub mod console;
+pub mod geometry;
+pub mod gesture;
+pub mod glyphs;
+pub mod orientation;
+pub mod raster;
+#[cfg(feature = "std")]
+pub mod render;
+pub mod separator;
+pub mod settings;
+pub mod seven_segment;
+pub mod timer;
+pub mod transition;
+pub mod validation;
+
+use calibration::Calibration;
+pub use glyphs::Symbol;
+use glyphs::{CH_LTR, DIGITS};
+use orientation::Orientation;
+use raster::{rasterize, Bitmap};
+use separator::{Separator, DOTS, SPARE_LEDS};
+
+/// Number of cells on the display.
+pub const CELLS: usize = 4;
+
+/// Number of IS31FL3731 drivers, two cells share each.
+pub const DRIVERS: usize = 3;
+
 pub struct ClockDisplay<I2C> {
-    pub drivers: [Option<IS31FL3731<I2C>>; 3],
+    pub drivers: [Option<IS31FL3731<I2C>>; DRIVERS],
+    /// Brightness compensation applied to every drawn segment.
+    pub calibration: Calibration,
+    /// How the board is mounted.
+    pub orientation: Orientation,
 }
 
 pub struct Segment {
     pub leds: &'static [u8],
+    pub outline: &'static [Point],
+    pub centroid: Point,
+    pub bounds: Rect,
+    /// Area in square tenths of a millimetre.
+    pub area: u16,
+}
+
+/// A position inside a cell, in tenths of a millimetre from the cell centre.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Point {
+    pub x: i16,
+    pub y: i16,
 }
 
-// maps segments to leds on one driver
-pub const SEGMENTS: [Segment; 44] = [
-    Segment { leds: &[1, 2, 18] },
-    Segment { leds: &[3, 19] },
-    Segment { leds: &[4, 20] },
-    Segment { leds: &[5, 6, 21] },
-    Segment { leds: &[0] },
-    Segment { leds: &[17, 34] },
-    Segment { leds: &[35] },
-    Segment { leds: &[36] },
-    Segment { leds: &[22, 37] },
-    Segment { leds: &[7] }, // 10
-    Segment { leds: &[16] },
-    Segment { leds: &[33, 50] },
-    Segment { leds: &[51] },
-    Segment { leds: &[52] },
-    Segment { leds: &[38, 53] },
-    Segment { leds: &[23] },
-    Segment { leds: &[49] },
-    Segment { leds: &[67] },
-    Segment { leds: &[68] },
-    Segment { leds: &[54] }, // 20
-    Segment { leds: &[32, 48] },
-    Segment {
-        leds: &[65, 66, 82],
-    },
-    Segment {
-        leds: &[69, 70, 85],
-    },
-    Segment { leds: &[39, 55] },
-    Segment { leds: &[81] },
-    Segment { leds: &[83] },
-    Segment { leds: &[84] },
-    Segment { leds: &[86] },
-    Segment { leds: &[64] },
-    Segment { leds: &[97, 98] }, // 30
-    Segment { leds: &[99] },
-    Segment { leds: &[100] },
-    Segment { leds: &[101, 102] },
-    Segment { leds: &[71] },
-    Segment { leds: &[80] },
-    Segment { leds: &[113, 114] },
-    Segment { leds: &[115] },
-    Segment { leds: &[116] },
-    Segment { leds: &[117, 118] },
-    Segment { leds: &[87] }, // 40
-    Segment {
-        leds: &[96, 129, 130],
-    },
-    Segment { leds: &[112, 131] },
-    Segment { leds: &[119, 132] },
-    Segment {
-        leds: &[103, 133, 134],
-    },
-];
-
-pub struct Symbol {
-    mask: [u8; 6],
+impl Point {
+    pub const fn new(x: i16, y: i16) -> Point {
+        Point { x, y }
+    }
 }
 
-const DIGITS: [Symbol; 10] = [
-    Symbol {
-        mask: [0x2f, 0x49, 0x69, 0x29, 0x49, 0xf],
-    }, // 0
-    Symbol {
-        mask: [0xc7, 0x30, 0x6, 0xc6, 0x30, 0x6],
-    }, // 1
-    Symbol {
-        mask: [0xf, 0x41, 0x6e, 0x27, 0x8, 0xf],
-    }, // 2
-    Symbol {
-        mask: [0xf, 0x41, 0x6e, 0xe, 0x41, 0xf],
-    }, // 3
-    Symbol {
-        mask: [0x29, 0x49, 0x6f, 0xe, 0x41, 0x8],
-    }, // 4
-    Symbol {
-        mask: [0x2f, 0x8, 0x67, 0xe, 0x41, 0xf],
-    }, // 5
-    Symbol {
-        mask: [0x2f, 0x8, 0x67, 0x2f, 0x49, 0xf],
-    }, // 6
-    Symbol {
-        mask: [0xf, 0x41, 0x4c, 0xc6, 0x30, 0x6],
-    }, // 7
-    Symbol {
-        mask: [0x2f, 0x49, 0x6f, 0x2f, 0x49, 0xf],
-    }, // 8
-    Symbol {
-        mask: [0x2f, 0x49, 0x6f, 0xe, 0x41, 0xf],
-    }, // 9
-];
-
-const PROGRESS_LTR: [Symbol; 6] = [
-    Symbol {
-        mask: [0x10, 0x4, 0x10, 0x10, 0x4, 0x0],
-    },
-    Symbol {
-        mask: [0x30, 0xc, 0x31, 0x31, 0xc, 0x0],
-    },
-    Symbol {
-        mask: [0x70, 0x1c, 0x33, 0x73, 0x1c, 0x0],
-    },
-    Symbol {
-        mask: [0xf0, 0x3c, 0x37, 0xf7, 0x3c, 0x0],
-    },
-    Symbol {
-        mask: [0xf0, 0x7d, 0x7f, 0xff, 0x7d, 0x0],
-    },
-    Symbol {
-        mask: [0xf0, 0xff, 0xff, 0xff, 0xff, 0x0],
-    },
-];
-
-const CH_LTR: [Symbol; 2] = [
-    Symbol {
-        mask: [0x2f, 0x9, 0x21, 0x21, 0x48, 0xf],
-    },
-    Symbol {
-        mask: [0x29, 0x49, 0x6f, 0x2f, 0x49, 0x9],
-    },
-];
+/// An axis aligned box, `min` being the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Rect {
+    pub const fn new(min: Point, max: Point) -> Rect {
+        Rect { min, max }
+    }
+
+    pub const fn width(&self) -> i16 {
+        self.max.x - self.min.x
+    }
+
+    pub const fn height(&self) -> i16 {
+        self.max.y - self.min.y
+    }
+
+    pub const fn contains(&self, point: Point) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+}
+
+// generated from segments.txt and clocklib-editor/leds.svg, see build.rs
+include!(concat!(env!("OUT_DIR"), "/segments.rs"));
 
 impl<I2C, E> ClockDisplay<I2C>
 where
     E: Debug,
     I2C: i2c::Read<Error = E> + i2c::Write<Error = E>,
 {
-    pub fn new(drivers: [Option<IS31FL3731<I2C>>; 3]) -> ClockDisplay<I2C> {
-        ClockDisplay { drivers }
+    pub fn new(drivers: [Option<IS31FL3731<I2C>>; DRIVERS]) -> ClockDisplay<I2C> {
+        ClockDisplay {
+            drivers,
+            calibration: Calibration::default(),
+            orientation: Orientation::default(),
+        }
     }
 
     pub fn setup(&mut self) -> Result<(), Error<E>> {
-        for driver in self.drivers.iter_mut().flatten() {
-            driver.setup()?;
-            driver.enable_leds(&[128, 135, 136, 143])?;
+        for (i, driver) in self.drivers.iter_mut().enumerate() {
+            if let Some(driver) = driver {
+                let disabled: heapless::Vec<u8, 4> = SPARE_LEDS
+                    .iter()
+                    .copied()
+                    .filter(|&led| !separator::is_dot(i, led))
+                    .collect();
+
+                driver.setup()?;
+                driver.enable_leds(&disabled)?;
+            }
         }
 
         Ok(())
@@ -162,7 +136,9 @@ where
     ) -> Result<(), Error<E>> {
         assert!(sub_display < 6);
 
+        let (sub_display, segment_id) = self.orientation.map(sub_display, segment_id);
         let segment = &SEGMENTS[segment_id];
+        let color = self.calibration.apply(segment_id, color);
         let driver_no = sub_display / 2;
         let sub_display = sub_display % 2;
 
@@ -181,33 +157,105 @@ where
         sub_display: u8,
         symbol_id: usize,
         color: u8,
+    ) -> Result<(), Error<E>> {
+        self.draw_glyph(sub_display, &DIGITS[symbol_id], color)
+    }
+
+    pub fn draw_CH(
+        &mut self,
+        sub_display: u8,
+        symbol_id: usize,
+        color: u8,
+    ) -> Result<(), Error<E>> {
+        self.draw_glyph(sub_display, &CH_LTR[symbol_id], color)
+    }
+
+    /// Draws a cell with every segment at its own level, as produced by a
+    /// [`transition::Transition`]. The colour is scaled by the level.
+    pub fn draw_levels(
+        &mut self,
+        sub_display: u8,
+        levels: &[u8; SEGMENTS.len()],
+        color: u8,
     ) -> Result<(), Error<E>> {
         assert!(sub_display < 4);
 
-        let symbol = &DIGITS[symbol_id];
-        let bits = symbol.mask.view_bits::<Lsb0>();
-        for (i, bit) in bits.iter().enumerate() {
-            if i < SEGMENTS.len() {
-                if bit == true {
-                    self.draw_segment(sub_display, i, color)?;
-                } else {
-                    self.draw_segment(sub_display, i, 0x00)?;
+        for (i, &level) in levels.iter().enumerate() {
+            let color = (color as u16 * level as u16 / 0xFF) as u8;
+            self.draw_segment(sub_display, i, color)?;
+        }
+
+        Ok(())
+    }
+
+    /// Shows a grayscale bitmap stretched over the whole display, see
+    /// [`raster`]. The colour is scaled by the pixel values.
+    pub fn draw_bitmap(&mut self, bitmap: &Bitmap, color: u8) -> Result<(), Error<E>> {
+        let mut cells = [[0; SEGMENTS.len()]; CELLS];
+        rasterize(bitmap, &mut cells);
+
+        for (i, levels) in cells.iter().enumerate() {
+            self.draw_levels(i as u8, levels, color)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws a 7-segment pattern, see [`seven_segment`].
+    pub fn draw_seven_segment(
+        &mut self,
+        sub_display: u8,
+        bits: u8,
+        color: u8,
+    ) -> Result<(), Error<E>> {
+        self.draw_glyph(sub_display, &seven_segment::to_symbol(bits), color)
+    }
+
+    /// Lights the dots of `separator` with `color` and turns the others off.
+    pub fn draw_separator(&mut self, separator: Separator, color: u8) -> Result<(), Error<E>> {
+        let mut dots = separator.dots();
+        if self.orientation.mirrors().1 {
+            dots.reverse();
+        }
+
+        for (&(driver_no, led), lit) in DOTS.iter().zip(dots) {
+            if let Some(driver) = &mut self.drivers[driver_no] {
+                driver.set_color_byte(led, if lit { color } else { 0x00 })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lets the driver blink the separator with a period of `period` * 0.27s,
+    /// or stops it. The segments keep shining steadily.
+    pub fn blink_separator(&mut self, period: Option<u8>) -> Result<(), Error<E>> {
+        for (i, driver) in self.drivers.iter_mut().enumerate() {
+            if let Some(driver) = driver {
+                let mut blink = [0x00; 18];
+                let blink_bits = blink.view_bits_mut::<Lsb0>();
+                for &(driver_no, led) in &DOTS {
+                    if driver_no == i {
+                        blink_bits.set(led as usize, period.is_some());
+                    }
                 }
+
+                driver.set_blink(0, &blink)?;
+                driver.configure_blink(period)?;
             }
         }
 
         Ok(())
     }
-    
-    pub fn draw_CH(
+
+    pub fn draw_glyph(
         &mut self,
         sub_display: u8,
-        symbol_id: usize,
+        symbol: &Symbol,
         color: u8,
     ) -> Result<(), Error<E>> {
         assert!(sub_display < 4);
 
-        let symbol = &CH_LTR[symbol_id];
         let bits = symbol.mask.view_bits::<Lsb0>();
         for (i, bit) in bits.iter().enumerate() {
             if i < SEGMENTS.len() {
@@ -0,0 +1,50 @@
+//! Mounting the display rotated or mirrored.
+//!
+//! Drawing happens in the logical orientation, [`Orientation::map`] moves
+//! each segment to where it physically ends up, so glyphs need no second
+//! font.
+
+use crate::{CELLS, MIRROR_X, MIRROR_Y};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    /// The board is mounted upside down.
+    Rotated180,
+    /// Left and right are swapped, e.g. when viewed through a mirror.
+    MirroredHorizontally,
+    /// Top and bottom are swapped.
+    MirroredVertically,
+}
+
+impl Orientation {
+    /// Whether left and right, and top and bottom are swapped.
+    pub const fn mirrors(self) -> (bool, bool) {
+        match self {
+            Orientation::Normal => (false, false),
+            Orientation::Rotated180 => (true, true),
+            Orientation::MirroredHorizontally => (true, false),
+            Orientation::MirroredVertically => (false, true),
+        }
+    }
+
+    /// Maps a logical cell and segment to the physical ones.
+    pub fn map(self, sub_display: u8, segment_id: usize) -> (u8, usize) {
+        let (mirror_x, mirror_y) = self.mirrors();
+
+        let mut sub_display = sub_display;
+        let mut segment_id = segment_id;
+
+        // only the cells of the display are reversed, any beyond are left
+        if mirror_x && (sub_display as usize) < CELLS {
+            sub_display = CELLS as u8 - 1 - sub_display;
+            segment_id = MIRROR_X[segment_id];
+        }
+        if mirror_y {
+            segment_id = MIRROR_Y[segment_id];
+        }
+
+        (sub_display, segment_id)
+    }
+}
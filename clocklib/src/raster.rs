@@ -0,0 +1,80 @@
+//! Rasterising bitmaps onto the mosaic.
+//!
+//! The bitmap is stretched over the box around all cells of the display.
+//! Each segment gets the average of the pixels under it, weighted by how much
+//! of the segment they cover. Coverage is estimated by sampling the segment
+//! on a regular grid.
+
+use crate::geometry::contains;
+use crate::{Point, CELL_BOUNDS, CELL_PITCH, SEGMENTS};
+
+// distance between samples, in tenths of a millimetre
+const SAMPLE_STEP: i16 = 5;
+
+/// A grayscale image, one byte per pixel, row by row.
+pub struct Bitmap<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u8],
+}
+
+impl<'a> Bitmap<'a> {
+    pub fn new(width: usize, height: usize, pixels: &'a [u8]) -> Bitmap<'a> {
+        assert_eq!(pixels.len(), width * height);
+        Bitmap {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Computes the level of every segment of `cells` neighbouring cells from
+/// `bitmap`, which covers all of them.
+pub fn rasterize(bitmap: &Bitmap, cells: &mut [[u8; SEGMENTS.len()]]) {
+    if cells.is_empty() || bitmap.width == 0 || bitmap.height == 0 {
+        return;
+    }
+
+    let left = CELL_BOUNDS.min.x as i32;
+    let top = CELL_BOUNDS.min.y as i32;
+    let width = (cells.len() as i32 - 1) * CELL_PITCH as i32 + CELL_BOUNDS.width() as i32;
+    let height = CELL_BOUNDS.height() as i32;
+
+    for (cell, levels) in cells.iter_mut().enumerate() {
+        let offset = cell as i32 * CELL_PITCH as i32;
+
+        for (segment, level) in SEGMENTS.iter().zip(levels.iter_mut()) {
+            let mut sum: u32 = 0;
+            let mut samples: u32 = 0;
+
+            let mut y = segment.bounds.min.y + SAMPLE_STEP / 2;
+            while y <= segment.bounds.max.y {
+                let mut x = segment.bounds.min.x + SAMPLE_STEP / 2;
+                while x <= segment.bounds.max.x {
+                    if contains(segment.outline, Point::new(x, y)) {
+                        let px = (x as i32 + offset - left) * bitmap.width as i32 / width;
+                        let py = (y as i32 - top) * bitmap.height as i32 / height;
+                        let px = px.clamp(0, bitmap.width as i32 - 1) as usize;
+                        let py = py.clamp(0, bitmap.height as i32 - 1) as usize;
+
+                        sum += bitmap.pixel(px, py) as u32;
+                        samples += 1;
+                    }
+                    x += SAMPLE_STEP;
+                }
+                y += SAMPLE_STEP;
+            }
+
+            *level = if samples > 0 {
+                (sum / samples) as u8
+            } else {
+                0
+            };
+        }
+    }
+}
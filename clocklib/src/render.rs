@@ -0,0 +1,186 @@
+//! Host side rendering of the display, for screenshots and reviewing glyphs.
+//!
+//! [`Emulator`] stands in for the I2C bus of one IS31FL3731 and keeps its
+//! register memory, so a `ClockDisplay<Emulator>` can be drawn to like the
+//! real thing and then turned into an SVG with [`render_svg`].
+
+use core::convert::Infallible;
+use embedded_hal::blocking::i2c;
+use is31fl3731_driver::{addresses, config_registers};
+use std::fmt::Write as _;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::{ClockDisplay, CELL_BOUNDS, CELL_PITCH, SEGMENTS};
+
+const FRAME_SIZE: usize = addresses::COLOR_OFFSET as usize + 144;
+const FRAMES: usize = 8;
+
+// colours of a dark and a fully lit segment, as in the editor
+const OFF_COLOR: [u8; 3] = [20, 20, 20];
+const ON_COLOR: [u8; 3] = [255, 228, 6];
+
+/// Register memory of one IS31FL3731, written through its I2C interface.
+pub struct Emulator {
+    page: u8,
+    frames: [[u8; FRAME_SIZE]; FRAMES],
+    config: [u8; config_registers::ADC_RATE as usize + 1],
+}
+
+impl Emulator {
+    pub fn new() -> Emulator {
+        Emulator {
+            page: 0,
+            frames: [[0; FRAME_SIZE]; FRAMES],
+            config: [0; config_registers::ADC_RATE as usize + 1],
+        }
+    }
+
+    /// Returns the PWM value the chip currently drives `led` with, taking the
+    /// displayed frame, the LED enable bits and software shutdown into account.
+    pub fn led(&self, led: u8) -> u8 {
+        if self.config[config_registers::SHUTDOWN as usize] == 0 {
+            return 0;
+        }
+
+        let frame = &self.frames[(self.config[config_registers::FRAME as usize] & 0x07) as usize];
+        let enabled =
+            frame[addresses::ENABLE_OFFSET as usize + led as usize / 8] & (1 << (led % 8));
+        if enabled == 0 {
+            return 0;
+        }
+
+        frame[addresses::COLOR_OFFSET as usize + led as usize]
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        if self.page == addresses::CONFIG_BANK {
+            if let Some(r) = self.config.get_mut(register as usize) {
+                *r = value;
+            }
+        } else if let Some(frame) = self.frames.get_mut(self.page as usize) {
+            if let Some(r) = frame.get_mut(register as usize) {
+                *r = value;
+            }
+        }
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new()
+    }
+}
+
+impl i2c::Write for Emulator {
+    type Error = Infallible;
+
+    fn write(&mut self, _address: u8, bytes: &[u8]) -> Result<(), Infallible> {
+        match bytes {
+            [addresses::BANK_ADDRESS, page] => self.page = *page,
+            [register, values @ ..] => {
+                // the register address auto increments
+                for (i, &value) in values.iter().enumerate() {
+                    self.write_register(register.wrapping_add(i as u8), value);
+                }
+            }
+            [] => {}
+        }
+        Ok(())
+    }
+}
+
+impl i2c::Read for Emulator {
+    type Error = Infallible;
+
+    fn read(&mut self, _address: u8, buffer: &mut [u8]) -> Result<(), Infallible> {
+        buffer.fill(0);
+        Ok(())
+    }
+}
+
+/// Returns the brightness of every segment of every cell, left to right.
+/// Cells of missing drivers are left out.
+pub fn segment_brightness(display: &ClockDisplay<Emulator>) -> Vec<[u8; SEGMENTS.len()]> {
+    let mut cells = Vec::new();
+
+    for driver in display.drivers.iter().flatten() {
+        for sub_display in 0..2 {
+            let mut cell = [0; SEGMENTS.len()];
+            for (brightness, segment) in cell.iter_mut().zip(SEGMENTS.iter()) {
+                *brightness = segment
+                    .leds
+                    .iter()
+                    .map(|&led| driver.i2c.led(led + 8 * sub_display))
+                    .max()
+                    .unwrap_or(0);
+            }
+            cells.push(cell);
+        }
+    }
+
+    cells
+}
+
+/// Draws cells of segment brightness values side by side as an SVG image,
+/// using the segment outlines from the editor drawing.
+pub fn render_svg(cells: &[[u8; SEGMENTS.len()]]) -> String {
+    let (min_y, max_y) = (CELL_BOUNDS.min.y, CELL_BOUNDS.max.y);
+
+    let margin = CELL_PITCH / 20;
+    let width = CELL_PITCH as i32 * cells.len() as i32;
+    let height = (max_y - min_y + 2 * margin) as i32;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        -CELL_PITCH / 2,
+        min_y - margin,
+        width,
+        height
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="black"/>"#,
+        -CELL_PITCH / 2,
+        min_y - margin,
+        width,
+        height
+    )
+    .unwrap();
+
+    for (i, cell) in cells.iter().enumerate() {
+        writeln!(
+            svg,
+            r#"  <g transform="translate({} 0)">"#,
+            i as i32 * CELL_PITCH as i32
+        )
+        .unwrap();
+
+        for (segment, &brightness) in SEGMENTS.iter().zip(cell.iter()) {
+            let [r, g, b] = color(brightness);
+            write!(svg, r#"    <polygon points=""#).unwrap();
+            for (j, point) in segment.outline.iter().enumerate() {
+                let separator = if j == 0 { "" } else { " " };
+                write!(svg, "{}{},{}", separator, point.x, point.y).unwrap();
+            }
+            writeln!(svg, r#"" fill="rgb({},{},{})"/>"#, r, g, b).unwrap();
+        }
+
+        writeln!(svg, "  </g>").unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+fn color(brightness: u8) -> [u8; 3] {
+    let mut color = [0; 3];
+    for (c, (&off, &on)) in color.iter_mut().zip(OFF_COLOR.iter().zip(ON_COLOR.iter())) {
+        let mix = off as u32 * (255 - brightness as u32) + on as u32 * brightness as u32;
+        *c = (mix / 255) as u8;
+    }
+    color
+}
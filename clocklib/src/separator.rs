@@ -0,0 +1,45 @@
+//! The separator between hours and minutes.
+//!
+//! It is made of two dots wired to matrix positions no segment uses, so they
+//! can be lit on their own. Brightness is the colour passed when drawing,
+//! blinking is either done by redrawing ([`ClockDisplay::draw_separator`]
+//! with alternating colours) or by the driver ([`ClockDisplay::blink_separator`]).
+//!
+//! [`ClockDisplay::draw_separator`]: crate::ClockDisplay::draw_separator
+//! [`ClockDisplay::blink_separator`]: crate::ClockDisplay::blink_separator
+
+/// Matrix positions of each driver not used by any segment.
+pub const SPARE_LEDS: [u8; 4] = [128, 135, 136, 143];
+
+/// The upper and lower dot as driver and LED, listed in `segments.txt`.
+pub const DOTS: [(usize, u8); 2] = crate::SEPARATOR_DOTS;
+
+/// Which dots of the separator are lit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Separator {
+    /// Both dots, the usual `12:34`.
+    Colon,
+    /// Only the upper dot.
+    UpperDot,
+    /// Only the lower dot, like a decimal point.
+    LowerDot,
+    /// Nothing lit.
+    Off,
+}
+
+impl Separator {
+    /// Whether the upper and the lower dot are lit.
+    pub const fn dots(self) -> [bool; 2] {
+        match self {
+            Separator::Colon => [true, true],
+            Separator::UpperDot => [true, false],
+            Separator::LowerDot => [false, true],
+            Separator::Off => [false, false],
+        }
+    }
+}
+
+/// Whether `led` of `driver` belongs to the separator.
+pub fn is_dot(driver: usize, led: u8) -> bool {
+    DOTS.contains(&(driver, led))
+}
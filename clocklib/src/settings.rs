@@ -0,0 +1,615 @@
+//! User preferences and their record in flash.
+//!
+//! The firmware keeps the settings as fixed size records, all numbers
+//! little endian:
+//!
+//! ```text
+//! header   "MS" version:u8 length:u8 sequence:u32
+//! payload  length bytes, see below
+//! crc      crc32:u32                    over the header and the payload
+//! ```
+//!
+//! The rest of a record stays erased. The record with the highest sequence
+//! number is the current one, so records can be written to fresh flash one
+//! after the other and a sector only needs erasing once it is full. A
+//! [`Journal`] keeps track of them. Sequence numbers wrap around, a record
+//! is newer when less than half the range ahead.
+//!
+//! The payload of version 1:
+//!
+//! ```text
+//! brightness:u8 flags:u8 orientation:u8 utc_offset:i16
+//! alarm*4  hours:u8 minutes:u8 weekdays:u8 enabled:u8
+//! ```
+//!
+//! with flag bit 0 for the 12-hour format, 1 for leading zeros and 2 for
+//! digit transitions. Later versions only append fields, those missing from
+//! a record written by an older version keep their defaults.
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+use crate::alarm::{Alarm, EVERY_DAY, MAX_ALARMS};
+use crate::orientation::Orientation;
+
+pub const MAGIC: [u8; 2] = *b"MS";
+pub const VERSION: u8 = 1;
+
+/// Bytes taken by a record, including the unused tail.
+pub const RECORD_LEN: usize = 64;
+
+const HEADER_LEN: usize = 8;
+const CRC_LEN: usize = 4;
+const PAYLOAD_LEN: usize = 5 + MAX_ALARMS * 4;
+
+const FLAG_TWELVE_HOUR: u8 = 0x01;
+const FLAG_LEADING_ZERO: u8 = 0x02;
+const FLAG_TRANSITIONS: u8 = 0x04;
+
+const ORIENTATIONS: [Orientation; 4] = [
+    Orientation::Normal,
+    Orientation::Rotated180,
+    Orientation::MirroredHorizontally,
+    Orientation::MirroredVertically,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HourFormat {
+    TwentyFour,
+    /// 12 to 11, with an indicator after noon.
+    Twelve,
+}
+
+impl HourFormat {
+    /// The hours to show for `hours` from 0 to 23, and whether they are
+    /// after noon.
+    pub const fn convert(self, hours: u8) -> (u8, bool) {
+        match self {
+            HourFormat::TwentyFour => (hours, false),
+            HourFormat::Twelve => {
+                let pm = hours >= 12;
+                match hours % 12 {
+                    0 => (12, pm),
+                    hours => (hours, pm),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
+    /// Index into the brightness levels of the firmware.
+    pub brightness: u8,
+    pub hour_format: HourFormat,
+    /// Show hours below 10 with a leading zero instead of a dark cell.
+    pub leading_zero: bool,
+    /// Animate digits as they change.
+    pub transitions: bool,
+    pub orientation: Orientation,
+    /// Minutes the local time is ahead of UTC, set on the console and used by
+    /// the timesync example. The clock itself keeps local time.
+    pub utc_offset: i16,
+    pub alarms: [Alarm; MAX_ALARMS],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    /// Nothing was written to the record yet.
+    Erased,
+    BadMagic,
+    /// Written by a newer firmware.
+    UnsupportedVersion(u8),
+    /// The length does not fit in a record.
+    BadLength(u8),
+    /// The record was not written completely or got damaged.
+    BadChecksum,
+    /// A field is out of range.
+    InvalidValue,
+}
+
+impl Settings {
+    pub const DEFAULT: Settings = Settings {
+        brightness: 4,
+        hour_format: HourFormat::TwentyFour,
+        leading_zero: true,
+        transitions: true,
+        orientation: Orientation::Normal,
+        utc_offset: 0,
+        alarms: [Alarm::DEFAULT; MAX_ALARMS],
+    };
+
+    /// The record holding the settings, numbered `sequence`.
+    pub fn encode(&self, sequence: u32) -> [u8; RECORD_LEN] {
+        let mut record = [0xFF; RECORD_LEN];
+        record[..2].copy_from_slice(&MAGIC);
+        record[2] = VERSION;
+        record[3] = PAYLOAD_LEN as u8;
+        record[4..HEADER_LEN].copy_from_slice(&sequence.to_le_bytes());
+
+        let payload = &mut record[HEADER_LEN..HEADER_LEN + PAYLOAD_LEN];
+        let mut flags = 0;
+        if self.hour_format == HourFormat::Twelve {
+            flags |= FLAG_TWELVE_HOUR;
+        }
+        if self.leading_zero {
+            flags |= FLAG_LEADING_ZERO;
+        }
+        if self.transitions {
+            flags |= FLAG_TRANSITIONS;
+        }
+        let orientation = ORIENTATIONS
+            .iter()
+            .position(|&orientation| orientation == self.orientation)
+            .unwrap();
+        payload[0] = self.brightness;
+        payload[1] = flags;
+        payload[2] = orientation as u8;
+        payload[3..5].copy_from_slice(&self.utc_offset.to_le_bytes());
+        for (alarm, bytes) in self.alarms.iter().zip(payload[5..].chunks_mut(4)) {
+            bytes.copy_from_slice(&[
+                alarm.hours,
+                alarm.minutes,
+                alarm.weekdays,
+                alarm.enabled as u8,
+            ]);
+        }
+
+        let end = HEADER_LEN + PAYLOAD_LEN;
+        let crc = crc32(&record[..end]);
+        record[end..end + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// The settings in a record and its sequence number.
+    pub fn decode(record: &[u8]) -> Result<(Settings, u32), RecordError> {
+        if is_erased(record) {
+            return Err(RecordError::Erased);
+        }
+        if record.len() < HEADER_LEN || record[..2] != MAGIC {
+            return Err(RecordError::BadMagic);
+        }
+        if record[2] > VERSION {
+            return Err(RecordError::UnsupportedVersion(record[2]));
+        }
+        let length = record[3];
+        let end = HEADER_LEN + length as usize;
+        if end + CRC_LEN > record.len() {
+            return Err(RecordError::BadLength(length));
+        }
+        let crc = u32::from_le_bytes([
+            record[end],
+            record[end + 1],
+            record[end + 2],
+            record[end + 3],
+        ]);
+        if crc != crc32(&record[..end]) {
+            return Err(RecordError::BadChecksum);
+        }
+        let sequence = u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+
+        // fields beyond the payload of an older version keep their defaults
+        let mut fields = Settings::DEFAULT.encode(0);
+        let known = end.min(HEADER_LEN + PAYLOAD_LEN);
+        fields[HEADER_LEN..known].copy_from_slice(&record[HEADER_LEN..known]);
+        let payload = &fields[HEADER_LEN..HEADER_LEN + PAYLOAD_LEN];
+
+        let flags = payload[1];
+        let mut settings = Settings {
+            brightness: payload[0],
+            hour_format: if flags & FLAG_TWELVE_HOUR != 0 {
+                HourFormat::Twelve
+            } else {
+                HourFormat::TwentyFour
+            },
+            leading_zero: flags & FLAG_LEADING_ZERO != 0,
+            transitions: flags & FLAG_TRANSITIONS != 0,
+            orientation: *ORIENTATIONS
+                .get(payload[2] as usize)
+                .ok_or(RecordError::InvalidValue)?,
+            utc_offset: i16::from_le_bytes([payload[3], payload[4]]),
+            alarms: Settings::DEFAULT.alarms,
+        };
+        for (alarm, bytes) in settings.alarms.iter_mut().zip(payload[5..].chunks(4)) {
+            if bytes[0] >= 24 || bytes[1] >= 60 || bytes[2] & !EVERY_DAY != 0 || bytes[3] > 1 {
+                return Err(RecordError::InvalidValue);
+            }
+            *alarm = Alarm {
+                hours: bytes[0],
+                minutes: bytes[1],
+                weekdays: bytes[2],
+                enabled: bytes[3] == 1,
+            };
+        }
+
+        Ok((settings, sequence))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::DEFAULT
+    }
+}
+
+/// The settings records in a range of flash sectors, one per slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Journal {
+    offset: u32,
+    slots: usize,
+    slots_per_sector: usize,
+    erase_size: usize,
+    /// Slot and sequence number of the current record.
+    current: Option<(usize, u32)>,
+}
+
+impl Journal {
+    /// The journal in `sectors` sectors of `erase_size` bytes from `offset`.
+    pub const fn new(offset: u32, sectors: usize, erase_size: usize) -> Journal {
+        let slots_per_sector = erase_size / RECORD_LEN;
+        Journal {
+            offset,
+            slots: sectors * slots_per_sector,
+            slots_per_sector,
+            erase_size,
+            current: None,
+        }
+    }
+
+    /// Slot and sequence number of the current record, once loaded or stored.
+    pub const fn current(&self) -> Option<(usize, u32)> {
+        self.current
+    }
+
+    /// The settings in the current record, if there is a valid one. Records
+    /// that fail to decode are skipped.
+    pub fn load<F: ReadNorFlash>(&mut self, flash: &mut F) -> Result<Option<Settings>, F::Error> {
+        let mut newest: Option<(Settings, usize, u32)> = None;
+        let mut record = [0; RECORD_LEN];
+
+        for slot in 0..self.slots {
+            flash.read(self.slot_offset(slot), &mut record)?;
+            if let Ok((settings, sequence)) = Settings::decode(&record) {
+                if newest.map_or(true, |(_, _, newest)| is_newer(sequence, newest)) {
+                    newest = Some((settings, slot, sequence));
+                }
+            }
+        }
+
+        self.current = newest.map(|(_, slot, sequence)| (slot, sequence));
+        Ok(newest.map(|(settings, ..)| settings))
+    }
+
+    /// Writes `settings` to the next free slot after the current record,
+    /// erasing a sector when reaching it, and returns its slot and sequence
+    /// number.
+    pub fn store<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        settings: &Settings,
+    ) -> Result<(usize, u32), F::Error> {
+        let (mut slot, sequence) = self.current.map_or((0, 0), |(slot, sequence)| {
+            ((slot + 1) % self.slots, sequence.wrapping_add(1))
+        });
+        let mut record = [0; RECORD_LEN];
+
+        loop {
+            if slot % self.slots_per_sector == 0 {
+                let sector = self.slot_offset(slot);
+                flash.erase(sector, sector + self.erase_size as u32)?;
+                break;
+            }
+            flash.read(self.slot_offset(slot), &mut record)?;
+            if is_erased(&record) {
+                break;
+            }
+            slot = (slot + 1) % self.slots;
+        }
+
+        flash.write(self.slot_offset(slot), &settings.encode(sequence))?;
+        self.current = Some((slot, sequence));
+        Ok((slot, sequence))
+    }
+
+    fn slot_offset(&self, slot: usize) -> u32 {
+        self.offset + (slot * RECORD_LEN) as u32
+    }
+}
+
+/// Whether sequence number `a` comes after `b`.
+fn is_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// Whether a record is still erased flash and can be written.
+pub fn is_erased(record: &[u8]) -> bool {
+    record.iter().all(|&byte| byte == 0xFF)
+}
+
+/// The CRC-32 used by zip and Ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alarm::WORKDAYS;
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind};
+
+    // four slots per sector
+    const SECTOR: usize = 4 * RECORD_LEN;
+    const SECTORS: usize = 2;
+    const SLOTS: usize = SECTORS * 4;
+
+    /// Flash in RAM where writes can only clear bits, like NOR flash.
+    struct RamFlash {
+        data: [u8; SECTORS * SECTOR],
+        erases: usize,
+    }
+
+    impl RamFlash {
+        fn new() -> RamFlash {
+            RamFlash {
+                data: [0xFF; SECTORS * SECTOR],
+                erases: 0,
+            }
+        }
+
+        fn slot(&mut self, slot: usize) -> &mut [u8] {
+            &mut self.data[slot * RECORD_LEN..][..RECORD_LEN]
+        }
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.copy_from_slice(&self.data[offset as usize..][..bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = SECTOR;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.data[from as usize..to as usize].fill(0xFF);
+            self.erases += 1;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            for (cell, byte) in self.data[offset as usize..].iter_mut().zip(bytes) {
+                *cell &= byte;
+            }
+            Ok(())
+        }
+    }
+
+    fn custom() -> Settings {
+        let mut settings = Settings {
+            brightness: 2,
+            hour_format: HourFormat::Twelve,
+            leading_zero: false,
+            transitions: false,
+            orientation: Orientation::MirroredVertically,
+            utc_offset: -330,
+            ..Settings::DEFAULT
+        };
+        settings.alarms[1] = Alarm {
+            hours: 6,
+            minutes: 45,
+            weekdays: WORKDAYS,
+            enabled: true,
+        };
+        settings
+    }
+
+    fn with_brightness(brightness: u8) -> Settings {
+        Settings {
+            brightness,
+            ..Settings::DEFAULT
+        }
+    }
+
+    /// Sets the payload length of `record` and fixes up its checksum.
+    fn truncate(record: &mut [u8; RECORD_LEN], length: u8) {
+        record[3] = length;
+        record[HEADER_LEN + length as usize..].fill(0xFF);
+        let end = HEADER_LEN + length as usize;
+        let crc = crc32(&record[..end]);
+        record[end..end + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let record = custom().encode(42);
+        assert_eq!(Settings::decode(&record), Ok((custom(), 42)));
+        assert_eq!(
+            Settings::decode(&Settings::DEFAULT.encode(0)),
+            Ok((Settings::DEFAULT, 0))
+        );
+    }
+
+    #[test]
+    fn erased_and_foreign_records_are_rejected() {
+        assert_eq!(
+            Settings::decode(&[0xFF; RECORD_LEN]),
+            Err(RecordError::Erased)
+        );
+        assert_eq!(
+            Settings::decode(&[0; RECORD_LEN]),
+            Err(RecordError::BadMagic)
+        );
+
+        let mut record = custom().encode(1);
+        record[3] = (RECORD_LEN - HEADER_LEN) as u8;
+        assert_eq!(
+            Settings::decode(&record),
+            Err(RecordError::BadLength((RECORD_LEN - HEADER_LEN) as u8))
+        );
+    }
+
+    #[test]
+    fn corrupt_records_fail_the_checksum() {
+        let mut record = custom().encode(1);
+        record[HEADER_LEN] ^= 0x01;
+        assert_eq!(Settings::decode(&record), Err(RecordError::BadChecksum));
+
+        let mut record = custom().encode(1);
+        record[HEADER_LEN + PAYLOAD_LEN] ^= 0x80;
+        assert_eq!(Settings::decode(&record), Err(RecordError::BadChecksum));
+    }
+
+    #[test]
+    fn out_of_range_fields_are_rejected() {
+        let mut record = custom().encode(1);
+        record[HEADER_LEN + 2] = ORIENTATIONS.len() as u8;
+        truncate(&mut record, PAYLOAD_LEN as u8);
+        assert_eq!(Settings::decode(&record), Err(RecordError::InvalidValue));
+
+        let mut record = custom().encode(1);
+        record[HEADER_LEN + 5] = 24;
+        truncate(&mut record, PAYLOAD_LEN as u8);
+        assert_eq!(Settings::decode(&record), Err(RecordError::InvalidValue));
+    }
+
+    #[test]
+    fn shorter_payloads_keep_the_defaults() {
+        // only brightness, flags and orientation, as if from an older version
+        let mut record = custom().encode(7);
+        truncate(&mut record, 3);
+
+        let expected = Settings {
+            utc_offset: Settings::DEFAULT.utc_offset,
+            alarms: Settings::DEFAULT.alarms,
+            ..custom()
+        };
+        assert_eq!(Settings::decode(&record), Ok((expected, 7)));
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut record = custom().encode(1);
+        record[2] = VERSION + 1;
+        truncate(&mut record, PAYLOAD_LEN as u8);
+        assert_eq!(
+            Settings::decode(&record),
+            Err(RecordError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn journal_starts_empty() {
+        let mut flash = RamFlash::new();
+        let mut journal = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(journal.load(&mut flash), Ok(None));
+        assert_eq!(journal.current(), None);
+
+        assert_eq!(journal.store(&mut flash, &custom()), Ok((0, 0)));
+        let mut reloaded = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(reloaded.load(&mut flash), Ok(Some(custom())));
+        assert_eq!(reloaded.current(), Some((0, 0)));
+    }
+
+    #[test]
+    fn journal_appends_and_erases_a_sector_when_reaching_it() {
+        let mut flash = RamFlash::new();
+        let mut journal = Journal::new(0, SECTORS, SECTOR);
+
+        for brightness in 0..SLOTS as u8 + 1 {
+            journal
+                .store(&mut flash, &with_brightness(brightness))
+                .unwrap();
+        }
+        // both sectors on the way, then the first one again
+        assert_eq!(flash.erases, 3);
+        assert_eq!(journal.current(), Some((0, SLOTS as u32)));
+        assert!(is_erased(flash.slot(1)));
+        assert!(!is_erased(flash.slot(SLOTS - 1)));
+
+        let mut reloaded = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(
+            reloaded.load(&mut flash),
+            Ok(Some(with_brightness(SLOTS as u8)))
+        );
+        assert_eq!(reloaded.current(), journal.current());
+    }
+
+    #[test]
+    fn journal_skips_damaged_records() {
+        let mut flash = RamFlash::new();
+        let mut journal = Journal::new(0, SECTORS, SECTOR);
+        journal.store(&mut flash, &with_brightness(1)).unwrap();
+        journal.store(&mut flash, &with_brightness(2)).unwrap();
+        // as if power failed while writing the second record
+        flash.slot(1)[HEADER_LEN + PAYLOAD_LEN..].fill(0xFF);
+
+        let mut reloaded = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(reloaded.load(&mut flash), Ok(Some(with_brightness(1))));
+        assert_eq!(reloaded.current(), Some((0, 0)));
+
+        // the damaged slot is not erased, so the next record goes after it
+        assert_eq!(reloaded.store(&mut flash, &with_brightness(3)), Ok((2, 1)));
+        let mut reloaded = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(reloaded.load(&mut flash), Ok(Some(with_brightness(3))));
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_across_slots() {
+        let mut flash = RamFlash::new();
+        flash
+            .slot(5)
+            .copy_from_slice(&with_brightness(1).encode(u32::MAX - 1));
+        flash
+            .slot(6)
+            .copy_from_slice(&with_brightness(2).encode(u32::MAX));
+        flash.slot(0).copy_from_slice(&with_brightness(3).encode(0));
+
+        let mut journal = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(journal.load(&mut flash), Ok(Some(with_brightness(3))));
+        assert_eq!(journal.current(), Some((0, 0)));
+
+        let mut flash = RamFlash::new();
+        flash
+            .slot(6)
+            .copy_from_slice(&with_brightness(2).encode(u32::MAX));
+        let mut journal = Journal::new(0, SECTORS, SECTOR);
+        journal.load(&mut flash).unwrap();
+        assert_eq!(journal.store(&mut flash, &with_brightness(4)), Ok((7, 0)));
+        assert_eq!(journal.store(&mut flash, &with_brightness(5)), Ok((0, 1)));
+
+        let mut reloaded = Journal::new(0, SECTORS, SECTOR);
+        assert_eq!(reloaded.load(&mut flash), Ok(Some(with_brightness(5))));
+        assert_eq!(reloaded.current(), Some((0, 1)));
+    }
+
+    #[test]
+    fn journal_uses_its_offset() {
+        let mut flash = RamFlash::new();
+        let mut journal = Journal::new(SECTOR as u32, 1, SECTOR);
+        assert_eq!(journal.store(&mut flash, &custom()), Ok((0, 0)));
+        assert!(is_erased(flash.slot(0)));
+        assert_eq!(Settings::decode(flash.slot(4)), Ok((custom(), 0)));
+    }
+}
@@ -0,0 +1,119 @@
+//! Drawing classic 7-segment patterns.
+//!
+//! Bit 0 to 6 are the segments `a` to `g`, bit 7 is the decimal point, the
+//! layout used by most 7-segment drivers and formatters:
+//!
+//! ```text
+//!  aaa
+//! f   b
+//!  ggg
+//! e   c
+//!  ddd  dp
+//! ```
+//!
+//! Every bit lights a group of mosaic segments, segments where two strokes
+//! meet belong to both groups.
+
+use crate::glyph;
+use crate::glyphs::Symbol;
+
+pub const A: u8 = 1 << 0;
+pub const B: u8 = 1 << 1;
+pub const C: u8 = 1 << 2;
+pub const D: u8 = 1 << 3;
+pub const E: u8 = 1 << 4;
+pub const F: u8 = 1 << 5;
+pub const G: u8 = 1 << 6;
+pub const DP: u8 = 1 << 7;
+
+/// The mosaic segments lit by each bit, `a` first.
+pub const GROUPS: [Symbol; 8] = [
+    // a
+    glyph!([0, 1, 2, 3]),
+    // b
+    glyph!([8, 14, 19, 22]),
+    // c
+    glyph!([22, 27, 32, 38]),
+    // d
+    glyph!([40, 41, 42, 43]),
+    // e
+    glyph!([21, 24, 29, 35]),
+    // f
+    glyph!([5, 11, 16, 21]),
+    // g
+    glyph!([17, 18, 21, 22, 25, 26]),
+    // dp
+    glyph!([39]),
+];
+
+/// The digits 0 to 9 in 7-segment encoding.
+pub const DIGITS: [u8; 10] = [
+    A | B | C | D | E | F,
+    B | C,
+    A | B | D | E | G,
+    A | B | C | D | G,
+    B | C | F | G,
+    A | C | D | F | G,
+    A | C | D | E | F | G,
+    A | B | C,
+    A | B | C | D | E | F | G,
+    A | B | C | D | F | G,
+];
+
+/// The 7-segment pattern of a character, `None` for the ones it cannot
+/// show like `k`, `m` or `x`. Letters take the usual upper or lower case
+/// shape, whichever case is asked for.
+pub const fn from_char(c: char) -> Option<u8> {
+    let bits = match c {
+        '0'..='9' => DIGITS[c as usize - '0' as usize],
+        ' ' => 0,
+        '-' => G,
+        '_' => D,
+        'A' | 'a' => A | B | C | E | F | G,
+        'B' | 'b' => C | D | E | F | G,
+        'C' => A | D | E | F,
+        'c' => D | E | G,
+        'D' | 'd' => B | C | D | E | G,
+        'E' | 'e' => A | D | E | F | G,
+        'F' | 'f' => A | E | F | G,
+        'G' | 'g' => A | C | D | E | F,
+        'H' => B | C | E | F | G,
+        'h' => C | E | F | G,
+        'I' | 'i' => E | F,
+        'J' | 'j' => B | C | D | E,
+        'L' | 'l' => D | E | F,
+        'N' | 'n' => C | E | G,
+        'O' => A | B | C | D | E | F,
+        'o' => C | D | E | G,
+        'P' | 'p' => A | B | E | F | G,
+        'Q' | 'q' => A | B | C | F | G,
+        'R' | 'r' => E | G,
+        'S' | 's' => A | C | D | F | G,
+        'T' | 't' => D | E | F | G,
+        'U' => B | C | D | E | F,
+        'u' => C | D | E,
+        'Y' | 'y' => B | C | D | F | G,
+        _ => return None,
+    };
+    Some(bits)
+}
+
+/// Converts a 7-segment pattern to the symbol lighting its groups.
+pub const fn to_symbol(bits: u8) -> Symbol {
+    let mut mask = [0; 6];
+    let mut i = 0;
+
+    while i < GROUPS.len() {
+        if bits & (1 << i) != 0 {
+            let group = GROUPS[i].mask();
+            let mut j = 0;
+            while j < mask.len() {
+                mask[j] |= group[j];
+                j += 1;
+            }
+        }
+        i += 1;
+    }
+
+    Symbol::from_mask(mask)
+}
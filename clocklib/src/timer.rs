@@ -0,0 +1,189 @@
+//! Countdown timer and stopwatch arithmetic on millisecond timestamps.
+//!
+//! The firmware passes `embassy_time::Instant::now().as_millis()`, any
+//! monotonic clock in milliseconds will do.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Countdown {
+    duration: u32,
+    // time counted before the last start
+    elapsed: u32,
+    // when the countdown was last started, if it is running
+    started: Option<u64>,
+}
+
+impl Countdown {
+    /// A stopped countdown of `duration` milliseconds.
+    pub const fn new(duration: u32) -> Countdown {
+        Countdown {
+            duration,
+            elapsed: 0,
+            started: None,
+        }
+    }
+
+    pub const fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// Changes the duration and resets the countdown.
+    pub fn set_duration(&mut self, duration: u32) {
+        *self = Countdown::new(duration);
+    }
+
+    /// Starts or resumes counting down, a countdown of zero does not
+    /// start.
+    pub fn start(&mut self, now: u64) {
+        if self.started.is_none() && self.duration > 0 {
+            self.started = Some(now);
+        }
+    }
+
+    pub fn pause(&mut self, now: u64) {
+        self.elapsed = self.elapsed(now);
+        self.started = None;
+    }
+
+    /// Stops and goes back to the full duration.
+    pub fn reset(&mut self) {
+        *self = Countdown::new(self.duration);
+    }
+
+    pub const fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Whether the countdown was started and not reset since.
+    pub fn is_started(&self) -> bool {
+        self.started.is_some() || self.elapsed > 0
+    }
+
+    pub fn elapsed(&self, now: u64) -> u32 {
+        let running = self
+            .started
+            .map_or(0, |started| now.saturating_sub(started));
+        (self.elapsed as u64 + running).min(self.duration as u64) as u32
+    }
+
+    pub fn remaining(&self, now: u64) -> u32 {
+        self.duration - self.elapsed(now)
+    }
+
+    /// The remaining time in whole seconds, rounded up so zero is only shown
+    /// once the countdown ran out.
+    pub fn remaining_seconds(&self, now: u64) -> u32 {
+        (self.remaining(now) + 999) / 1000
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.duration > 0 && self.remaining(now) == 0
+    }
+}
+
+/// Lap times kept by a [`Stopwatch`], older ones are dropped.
+pub const MAX_LAPS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stopwatch {
+    // time counted before the last start
+    elapsed: u64,
+    // when the stopwatch was last started, if it is running
+    started: Option<u64>,
+    // elapsed time at the end of the last lap
+    lap_start: u64,
+    laps: [u64; MAX_LAPS],
+    lap_count: usize,
+}
+
+impl Stopwatch {
+    pub const fn new() -> Stopwatch {
+        Stopwatch {
+            elapsed: 0,
+            started: None,
+            lap_start: 0,
+            laps: [0; MAX_LAPS],
+            lap_count: 0,
+        }
+    }
+
+    /// Starts or resumes counting.
+    pub fn start(&mut self, now: u64) {
+        if self.started.is_none() {
+            self.started = Some(now);
+        }
+    }
+
+    pub fn stop(&mut self, now: u64) {
+        self.elapsed = self.elapsed(now);
+        self.started = None;
+    }
+
+    /// Stops, clearing the time and the laps.
+    pub fn reset(&mut self) {
+        *self = Stopwatch::new();
+    }
+
+    pub const fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Milliseconds counted so far.
+    pub fn elapsed(&self, now: u64) -> u64 {
+        self.elapsed
+            + self
+                .started
+                .map_or(0, |started| now.saturating_sub(started))
+    }
+
+    /// Ends the current lap and records its time.
+    pub fn lap(&mut self, now: u64) {
+        let elapsed = self.elapsed(now);
+        if self.lap_count == MAX_LAPS {
+            self.laps.copy_within(1.., 0);
+            self.lap_count -= 1;
+        }
+        self.laps[self.lap_count] = elapsed - self.lap_start;
+        self.lap_count += 1;
+        self.lap_start = elapsed;
+    }
+
+    /// The recorded lap times, oldest first.
+    pub fn laps(&self) -> &[u64] {
+        &self.laps[..self.lap_count]
+    }
+}
+
+impl Default for Stopwatch {
+    fn default() -> Self {
+        Stopwatch::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_runs_out() {
+        let mut countdown = Countdown::new(3000);
+        countdown.start(1000);
+        assert_eq!(countdown.remaining_seconds(1001), 3);
+        assert!(!countdown.is_expired(3999));
+        assert!(countdown.is_expired(4000));
+
+        // stays expired once stopped at zero
+        countdown.pause(4000);
+        assert!(countdown.is_expired(10_000));
+        countdown.reset();
+        assert!(!countdown.is_expired(10_000));
+    }
+
+    #[test]
+    fn zero_countdown_does_not_start() {
+        let mut countdown = Countdown::new(0);
+        countdown.start(1000);
+        assert!(!countdown.is_running());
+        assert!(!countdown.is_started());
+        assert!(!countdown.is_expired(5000));
+    }
+}
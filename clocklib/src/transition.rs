@@ -0,0 +1,219 @@
+//! Segment level animations between two glyphs.
+//!
+//! A [`Transition`] is stepped with a frame counter from `0` (showing the old
+//! glyph) to [`Transition::frames`] (showing the new one). Each frame gives a
+//! level per segment, which [`ClockDisplay::draw_levels`] scales the colour
+//! with.
+//!
+//! [`ClockDisplay::draw_levels`]: crate::ClockDisplay::draw_levels
+
+use crate::geometry::distance_squared;
+use crate::{Point, Symbol, CELL_BOUNDS, SEGMENTS};
+
+/// Fully lit segment level.
+pub const FULL: u8 = 0xFF;
+
+// resolution of the moment a segment switches within the transition
+const SCALE: u32 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Switches to the new glyph at once.
+    Cut,
+    /// Fades the old segments out while fading the new ones in.
+    Crossfade,
+    /// Sweeps an edge across the cell, switching segments as it passes.
+    Wipe(Direction),
+    /// Switches the segments one by one in a random order picked by the seed.
+    Dissolve(u32),
+    /// Shrinks the old glyph towards the new one while growing the new glyph
+    /// out of the old one.
+    Morph,
+}
+
+/// Direction a wipe travels in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    LeftToRight,
+    RightToLeft,
+    TopToBottom,
+    BottomToTop,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Transition {
+    from: Symbol,
+    to: Symbol,
+    style: Style,
+    frames: u8,
+}
+
+impl Transition {
+    pub const fn new(from: Symbol, to: Symbol, style: Style, frames: u8) -> Transition {
+        Transition {
+            from,
+            to,
+            style,
+            frames,
+        }
+    }
+
+    /// The last frame, which shows the new glyph.
+    pub fn frames(&self) -> u8 {
+        self.frames
+    }
+
+    /// Returns the level of every segment at `frame`.
+    pub fn levels(&self, frame: u8) -> [u8; SEGMENTS.len()] {
+        let mut levels = [0; SEGMENTS.len()];
+        let done = frame >= self.frames;
+        let progress = if done {
+            SCALE
+        } else {
+            frame as u32 * SCALE / self.frames as u32
+        };
+
+        // how far the morph has to reach, so both the appearing and the
+        // disappearing segments are spread over all frames
+        let reach = |appearing: bool| {
+            (0..SEGMENTS.len())
+                .filter(|&s| self.to.is_lit(s) == appearing && self.from.is_lit(s) != appearing)
+                .map(|s| self.distance_to_other(s))
+                .max()
+                .unwrap_or(0)
+                + 1
+        };
+        let reach = if self.style == Style::Morph {
+            (reach(true), reach(false))
+        } else {
+            (1, 1)
+        };
+
+        for (segment, level) in levels.iter_mut().enumerate() {
+            let from = self.from.is_lit(segment);
+            let to = self.to.is_lit(segment);
+
+            *level = match (from, to) {
+                (false, false) => 0,
+                (true, true) => FULL,
+                _ if done => level_of(to),
+                _ => match self.style {
+                    Style::Crossfade => {
+                        let faded = (FULL as u32 * progress / SCALE) as u8;
+                        if to {
+                            faded
+                        } else {
+                            FULL - faded
+                        }
+                    }
+                    _ => {
+                        let switched = self.switch_at(segment, to, reach) < progress;
+                        level_of(switched == to)
+                    }
+                },
+            };
+        }
+
+        levels
+    }
+
+    // the moment in 0..=SCALE at which a changing segment switches
+    fn switch_at(&self, segment: usize, appearing: bool, reach: (u32, u32)) -> u32 {
+        match self.style {
+            Style::Cut | Style::Crossfade => 0,
+            Style::Wipe(direction) => {
+                let (position, min, max) = match direction {
+                    Direction::LeftToRight | Direction::RightToLeft => {
+                        extent(segment, |point| point.x)
+                    }
+                    Direction::TopToBottom | Direction::BottomToTop => {
+                        extent(segment, |point| point.y)
+                    }
+                };
+                let along = ((position - min) as u32 * SCALE) / (max - min) as u32;
+
+                match direction {
+                    Direction::LeftToRight | Direction::TopToBottom => along,
+                    Direction::RightToLeft | Direction::BottomToTop => SCALE - along,
+                }
+            }
+            Style::Dissolve(seed) => hash(segment as u32 ^ seed.rotate_left(8)) % SCALE,
+            Style::Morph => {
+                let reach = if appearing { reach.0 } else { reach.1 };
+                let along = self.distance_to_other(segment) * SCALE / reach;
+
+                if appearing {
+                    along
+                } else {
+                    SCALE - along
+                }
+            }
+        }
+    }
+}
+
+impl Transition {
+    // distance from a changing segment to the closest segment of the glyph
+    // it is not part of
+    fn distance_to_other(&self, segment: usize) -> u32 {
+        let other = if self.to.is_lit(segment) {
+            &self.from
+        } else {
+            &self.to
+        };
+        let centroid = SEGMENTS[segment].centroid;
+
+        (0..SEGMENTS.len())
+            .filter(|&s| other.is_lit(s))
+            .map(|s| isqrt(distance_squared(centroid, SEGMENTS[s].centroid)))
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+fn level_of(lit: bool) -> u8 {
+    if lit {
+        FULL
+    } else {
+        0
+    }
+}
+
+// position of a segment along an axis and the range covered by the cell
+fn extent(segment: usize, axis: impl Fn(Point) -> i16) -> (i32, i32, i32) {
+    let (min, max) = (axis(CELL_BOUNDS.min) as i32, axis(CELL_BOUNDS.max) as i32);
+    (
+        axis(SEGMENTS[segment].centroid) as i32,
+        min,
+        max.max(min + 1),
+    )
+}
+
+fn isqrt(n: u32) -> u32 {
+    let mut root = 0;
+    let mut bit = 1 << 30;
+    let mut n = n;
+
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if n >= root + bit {
+            n -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    root
+}
+
+fn hash(mut x: u32) -> u32 {
+    x = x.wrapping_mul(0x9E37_79B1);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x2C1B_3C6D);
+    x ^= x >> 12;
+    x
+}
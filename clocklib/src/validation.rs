@@ -0,0 +1,195 @@
+//! Consistency checks of the segment layout and the glyph tables.
+//!
+//! The checks are `const fn`s and run when clocklib is compiled, so a typo
+//! in `segments.txt` or a hand edited mask fails the build instead of
+//! lighting the wrong LED.
+
+use crate::glyphs::{Symbol, TABLES};
+use crate::separator::{DOTS, SPARE_LEDS};
+use crate::{seven_segment, DRIVERS, SEGMENTS};
+
+/// LEDs in the matrix of one driver.
+pub const MATRIX_LEDS: usize = 144;
+
+/// Offset of the LEDs of the second cell on a driver.
+pub const SECOND_CELL_OFFSET: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The segment has no LED.
+    NoLeds { segment: usize },
+    /// The LED is outside the matrix in the second cell.
+    OutOfRange { segment: usize, led: u8 },
+    /// The LED is used twice on the same driver, counting both cells.
+    DuplicateLed { segment: usize, led: u8 },
+    /// The LED is one of the spare LEDs, which are disabled or used by the
+    /// separator.
+    SpareLed { segment: usize, led: u8 },
+    /// A separator dot is not one of the spare LEDs.
+    DotNotSpare { dot: usize },
+    /// A separator dot is on a driver the display does not have.
+    DotDriverOutOfRange { dot: usize },
+    /// Two separator dots are the same LED.
+    DuplicateDot { dot: usize },
+    /// A glyph has bits set beyond the last segment.
+    GlyphOutOfRange { table: &'static str, index: usize },
+}
+
+impl LayoutError {
+    pub const fn message(&self) -> &'static str {
+        match self {
+            LayoutError::NoLeds { .. } => "segment without LEDs",
+            LayoutError::OutOfRange { .. } => "LED of the second cell outside the matrix",
+            LayoutError::DuplicateLed { .. } => "LED used twice",
+            LayoutError::SpareLed { .. } => "segment uses a spare LED",
+            LayoutError::DotNotSpare { .. } => "separator dot is not a spare LED",
+            LayoutError::DotDriverOutOfRange { .. } => "separator dot on a missing driver",
+            LayoutError::DuplicateDot { .. } => "separator dot used twice",
+            LayoutError::GlyphOutOfRange { .. } => "glyph lights a segment that does not exist",
+        }
+    }
+}
+
+/// Checks that every segment has LEDs, that the LEDs of both cells on a
+/// driver fit the matrix without overlapping each other or the spare LEDs,
+/// and checks the separator dots with [`check_dots`].
+pub const fn check_layout() -> Result<(), LayoutError> {
+    let mut used = [false; MATRIX_LEDS];
+
+    let mut segment = 0;
+    while segment < SEGMENTS.len() {
+        let leds = SEGMENTS[segment].leds;
+        if leds.is_empty() {
+            return Err(LayoutError::NoLeds { segment });
+        }
+
+        let mut i = 0;
+        while i < leds.len() {
+            let led = leds[i];
+            let mut cell = 0;
+            while cell < 2 {
+                let index = led as usize + cell * SECOND_CELL_OFFSET;
+                if index >= MATRIX_LEDS {
+                    return Err(LayoutError::OutOfRange { segment, led });
+                }
+                if is_spare(index) {
+                    return Err(LayoutError::SpareLed { segment, led });
+                }
+                if used[index] {
+                    return Err(LayoutError::DuplicateLed { segment, led });
+                }
+                used[index] = true;
+                cell += 1;
+            }
+            i += 1;
+        }
+        segment += 1;
+    }
+
+    check_dots(&DOTS)
+}
+
+/// Checks that the separator dots are distinct spare LEDs of existing
+/// drivers.
+pub const fn check_dots(dots: &[(usize, u8)]) -> Result<(), LayoutError> {
+    let mut dot = 0;
+    while dot < dots.len() {
+        let (driver, led) = dots[dot];
+        if driver >= DRIVERS {
+            return Err(LayoutError::DotDriverOutOfRange { dot });
+        }
+        if !is_spare(led as usize) {
+            return Err(LayoutError::DotNotSpare { dot });
+        }
+        let mut other = 0;
+        while other < dot {
+            if dots[other].0 == driver && dots[other].1 == led {
+                return Err(LayoutError::DuplicateDot { dot });
+            }
+            other += 1;
+        }
+        dot += 1;
+    }
+
+    Ok(())
+}
+
+/// Checks that the glyphs of `table` only light existing segments.
+pub const fn check_glyphs(table: &'static str, glyphs: &[Symbol]) -> Result<(), LayoutError> {
+    let mut index = 0;
+    while index < glyphs.len() {
+        let mask = glyphs[index].mask();
+        let mut bit = SEGMENTS.len();
+        while bit < mask.len() * 8 {
+            if mask[bit / 8] & (1 << (bit % 8)) != 0 {
+                return Err(LayoutError::GlyphOutOfRange { table, index });
+            }
+            bit += 1;
+        }
+        index += 1;
+    }
+
+    Ok(())
+}
+
+/// Runs [`check_layout`] and [`check_glyphs`] on every table of clocklib.
+pub const fn check_all() -> Result<(), LayoutError> {
+    if let Err(error) = check_layout() {
+        return Err(error);
+    }
+
+    let mut table = 0;
+    while table < TABLES.len() {
+        let (name, glyphs) = TABLES[table];
+        if let Err(error) = check_glyphs(name, glyphs) {
+            return Err(error);
+        }
+        table += 1;
+    }
+
+    check_glyphs("seven_segment::GROUPS", &seven_segment::GROUPS)
+}
+
+const fn is_spare(index: usize) -> bool {
+    let mut i = 0;
+    while i < SPARE_LEDS.len() {
+        if SPARE_LEDS[i] as usize == index {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+const _: () = if let Err(error) = check_all() {
+    panic!("{}", error.message());
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_of_clocklib() {
+        assert_eq!(check_all(), Ok(()));
+    }
+
+    #[test]
+    fn separator_dots() {
+        assert_eq!(check_dots(&[(0, 136), (0, 143)]), Ok(()));
+        assert_eq!(check_dots(&[(0, 136), (DRIVERS - 1, 136)]), Ok(()));
+
+        assert_eq!(
+            check_dots(&[(0, 136), (DRIVERS, 143)]),
+            Err(LayoutError::DotDriverOutOfRange { dot: 1 })
+        );
+        assert_eq!(
+            check_dots(&[(0, 136), (0, 137)]),
+            Err(LayoutError::DotNotSpare { dot: 1 })
+        );
+        assert_eq!(
+            check_dots(&[(1, 143), (1, 143)]),
+            Err(LayoutError::DuplicateDot { dot: 1 })
+        );
+    }
+}
@@ -113,6 +113,31 @@ where
             .write(self.address, &[addresses::ENABLE_OFFSET + index, value])?;
         Ok(())
     }
+
+    /// Sets the blink bits of frame `page`, one bit per LED in the order of
+    /// [`set_onoff`](Self::set_onoff). They take effect once blinking is
+    /// enabled with [`configure_blink`](Self::configure_blink).
+    pub fn set_blink(&mut self, page: u8, blink: &[u8; 18]) -> Result<(), Error<E>> {
+        self.select_page(page)?;
+        let mut buf = [0u8; 19];
+        buf[0] = addresses::BLINK_OFFSET;
+        buf[1..].copy_from_slice(blink);
+        self.i2c.write(self.address, &buf)?;
+        Ok(())
+    }
+
+    /// Enables blinking of the LEDs with their blink bit set, with a period of
+    /// `period` * 0.27s, or disables it. Leaves page 0 selected.
+    pub fn configure_blink(&mut self, period: Option<u8>) -> Result<(), Error<E>> {
+        let value = match period {
+            Some(period) if period > 7 => return Err(Error::InvalidBlinkPeriod(period)),
+            Some(period) => 0x08 | period,
+            None => 0x00,
+        };
+        self.write_register(addresses::CONFIG_BANK, config_registers::BLINK, value)?;
+        self.select_page(0)?;
+        Ok(())
+    }
 }
 
 pub mod config_registers {
@@ -150,6 +175,7 @@ pub enum Error<I2cError> {
     I2cError(I2cError),
     InvalidLocation(u8),
     InvalidFrame(u8),
+    InvalidBlinkPeriod(u8),
 }
 
 impl<E> From<E> for Error<E> {